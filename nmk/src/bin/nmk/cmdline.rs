@@ -1,12 +1,26 @@
 use std::path::PathBuf;
+use std::process::exit;
 use std::time::Instant;
 
+use log::{debug, error};
 use once_cell::sync::Lazy;
-use structopt::clap::Shell;
+use structopt::clap::{AppSettings, Shell};
 use structopt::StructOpt;
+use strum::VariantNames;
 
+use nmk::config::Config;
+use nmk::history;
+use nmk::home::NmkHome;
+use nmk::shell_env;
+use nmk::timing;
+use nmk::tmux::socket;
+use nmk::tmux::status::Segment;
+
+use crate::tmux;
 use crate::version::get_verbose_version;
 
+const DEFAULT_SOCKET: &str = "nmk";
+
 static VERSION: Lazy<String> = Lazy::new(|| get_verbose_version().expect("missing version info"));
 
 #[derive(Debug, StructOpt)]
@@ -20,10 +34,12 @@ pub struct CmdOpt {
     #[structopt(
         short = "L",
         long = "socket",
-        default_value = "nmk",
         value_name = "name",
-        help = "Use a different tmux socket name"
+        help = "Use a different tmux socket name, {host}, {container} and {project} are expanded [default: nmk]"
     )]
+    pub socket_template: Option<String>,
+    /// Socket name expanded from `socket_template` or `socket` of nmk.toml
+    #[structopt(skip)]
     pub socket: String,
     #[structopt(
         long,
@@ -31,6 +47,27 @@ pub struct CmdOpt {
         help = "Specify an alternative tmux configuration file"
     )]
     pub tmux_conf: Option<PathBuf>,
+    #[structopt(
+        short,
+        long,
+        value_name = "name",
+        help = "Attach to or create session of profile defined in nmk.toml"
+    )]
+    pub profile: Option<String>,
+    #[structopt(
+        short,
+        long,
+        value_name = "name",
+        conflicts_with = "profile",
+        help = "Attach to session by name or create it"
+    )]
+    pub attach: Option<String>,
+    #[structopt(
+        long,
+        conflicts_with_all = &["attach", "profile"],
+        help = "Create a new session instead of attaching to an existing one"
+    )]
+    pub new: bool,
     #[structopt(short = "l", long, help = "Start zsh login shell")]
     pub login: bool,
     #[structopt(long, help = "Detach the client when the session is destroyed")]
@@ -41,6 +78,13 @@ pub struct CmdOpt {
     pub unicode: bool,
     #[structopt(long, help = "Prints usage time")]
     pub usage: bool,
+    #[structopt(
+        long,
+        value_name = "format",
+        possible_values = timing::Format::VARIANTS,
+        help = "Prints duration of each startup phase to stderr, as text by default"
+    )]
+    pub profile_startup: Option<Option<timing::Format>>,
     #[structopt(long, help = "Display Message of The Day")]
     pub motd: bool,
     #[structopt(long, help = "Render tmux config then exit")]
@@ -54,17 +98,69 @@ pub struct CmdOpt {
 #[derive(Debug, StructOpt)]
 pub enum SubCommand {
     #[structopt(about = "Backup files to do clean install")]
-    Backup,
+    Backup(Backup),
+    #[structopt(about = "Copy stdin to or paste from system clipboard")]
+    Clipboard(Clipboard),
     #[structopt(about = "Generate tab-completion scripts for your shell")]
     Completions(Completion),
+    #[structopt(about = "Print environment variables set up by nmk without starting anything")]
+    Env(Env),
+    #[structopt(about = "Manage shell and tmux history")]
+    History(History),
     #[structopt(about = "Display entrypoint information")]
     Info,
+    #[structopt(about = "Print effective key bindings grouped by key table")]
+    Keys(Keys),
+    #[structopt(about = "Open session from layout file")]
+    Open(Open),
+    #[structopt(about = "Render tmux config again and load it into running tmux server")]
+    Reload,
+    #[structopt(about = "Render tmux config, optionally check it with installed tmux")]
+    Render(Render),
+    #[structopt(about = "Save or restore tmux sessions")]
+    Session(Session),
     #[structopt(about = "Setup components from files")]
     Setup(Setup),
-    #[structopt(about = "Run tmux command on running tmux server")]
+    #[structopt(about = "Print status line segment, called by tmux")]
+    StatusSegment(StatusSegment),
+    #[structopt(about = "List, preview or set colour theme")]
+    Theme(ThemeCommand),
+    #[structopt(about = "Query or run tmux command on running tmux server")]
     Tmux(Tmux),
 }
 
+#[derive(Debug, StructOpt)]
+pub struct Backup {
+    #[structopt(
+        short,
+        long,
+        value_name = "dir",
+        help = "Write archive to this directory instead of configured one"
+    )]
+    pub output_dir: Option<PathBuf>,
+    #[structopt(
+        short,
+        long,
+        value_name = "n",
+        help = "Number of archives to keep, 0 means keep all"
+    )]
+    pub keep: Option<usize>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Clipboard {
+    #[structopt(about = "Copy stdin to clipboard")]
+    Copy,
+    #[structopt(about = "Print clipboard contents")]
+    Paste(ClipboardPaste),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ClipboardPaste {
+    #[structopt(long, help = "Paste into current tmux pane instead of stdout")]
+    pub tmux: bool,
+}
+
 #[derive(Debug, StructOpt)]
 pub struct Completion {
     #[structopt(short, long, help = "write to file instead of stdout")]
@@ -73,6 +169,106 @@ pub struct Completion {
     pub shell: Shell,
 }
 
+#[derive(Debug, StructOpt)]
+pub struct Keys {
+    #[structopt(
+        short,
+        long,
+        value_name = "name",
+        help = "Only show bindings of key table"
+    )]
+    pub table: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum History {
+    #[structopt(about = "Merge history files into nmk history file")]
+    Merge(HistoryMerge),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Env {
+    #[structopt(
+        long,
+        default_value = "zsh",
+        possible_values = shell_env::Format::VARIANTS,
+        help = "Output format"
+    )]
+    pub shell: shell_env::Format,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct HistoryMerge {
+    #[structopt(
+        short,
+        long,
+        possible_values = history::Format::VARIANTS,
+        help = "History format, detected from files if not given"
+    )]
+    pub format: Option<history::Format>,
+    #[structopt(
+        short,
+        long,
+        value_name = "file",
+        help = "Write to this file instead of nmk history file"
+    )]
+    pub output: Option<PathBuf>,
+    #[structopt(long, help = "Do not merge current nmk history file")]
+    pub no_current: bool,
+    #[structopt(long, help = "Drop commands matching configured secret patterns")]
+    pub filter_secrets: bool,
+    #[structopt(
+        short,
+        long,
+        value_name = "pattern",
+        help = "Drop commands matching glob pattern, can be given multiple times"
+    )]
+    pub exclude: Vec<String>,
+    #[structopt(long, help = "Print result to stdout instead of writing file")]
+    pub stdout: bool,
+    #[structopt(required = true, value_name = "files", help = "History files to merge")]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Open {
+    #[structopt(
+        value_name = "layout",
+        help = "Layout file or name of file in $NMK_HOME/layouts, default to ./.nmk-layout.toml"
+    )]
+    pub layout: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Session {
+    #[structopt(about = "Save sessions, windows and panes of tmux server")]
+    Save(SessionSave),
+    #[structopt(about = "Recreate saved sessions that are not running")]
+    Restore,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SessionSave {
+    #[structopt(long, help = "Save pane contents")]
+    pub scrollback: bool,
+    #[structopt(
+        long,
+        help = "Save only if autosave is enabled and last save is older than autosave interval"
+    )]
+    pub auto: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Render {
+    #[structopt(
+        long,
+        help = "Report lines rejected by a throwaway tmux server instead of printing config"
+    )]
+    pub check: bool,
+    #[structopt(long, help = "Print config with lines rejected by tmux commented out")]
+    pub strip: bool,
+}
+
 #[derive(Debug, StructOpt)]
 pub struct Setup {
     #[structopt(short, long, value_name = "file", help = "Setup dotfiles")]
@@ -81,14 +277,143 @@ pub struct Setup {
     pub entrypoint: Option<PathBuf>,
     #[structopt(short, long, value_name = "file", help = "Setup vendor files")]
     pub vendor: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Compile bundled terminfo entries into $NMK_HOME/terminfo"
+    )]
+    pub terminfo: bool,
 }
 
 #[derive(Debug, StructOpt)]
+pub struct StatusSegment {
+    #[structopt(possible_values = Segment::VARIANTS)]
+    pub segment: Segment,
+    #[structopt(value_name = "path", help = "Pane current path, used by git segment")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ThemeCommand {
+    #[structopt(about = "List builtin themes and themes in $NMK_HOME/themes")]
+    List,
+    #[structopt(about = "Show colours of theme in this terminal")]
+    Preview(ThemePreview),
+    #[structopt(about = "Use theme and apply it to running tmux server")]
+    Set(ThemeSet),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ThemePreview {
+    #[structopt(
+        long,
+        help = "Show true colours even if terminal is not detected to support them"
+    )]
+    pub true_color: bool,
+    #[structopt(value_name = "name", help = "Theme name, default to current theme")]
+    pub name: Option<String>,
+}
+
+impl SubCommand {
+    /// Subcommand talks to running tmux server or renders config that refers to it
+    pub fn needs_socket(&self) -> bool {
+        matches!(
+            self,
+            SubCommand::Keys(_)
+                | SubCommand::Open(_)
+                | SubCommand::Reload
+                | SubCommand::Session(_)
+                | SubCommand::Theme(ThemeCommand::Set(_))
+                | SubCommand::Tmux(_)
+        )
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ThemeSet {
+    #[structopt(value_name = "name", help = "Theme name")]
+    pub name: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(settings = &[AppSettings::AllowLeadingHyphen, AppSettings::DisableVersion])]
 pub struct Tmux {
-    #[structopt(value_name = "command", help = "Tmux command")]
-    pub args: Vec<String>,
+    #[structopt(subcommand)]
+    pub cmd: Option<TmuxCommand>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum TmuxCommand {
+    #[structopt(about = "List sessions")]
+    Ls(TmuxList),
+    #[structopt(about = "List windows")]
+    Windows(TmuxList),
+    #[structopt(about = "List panes")]
+    Panes(TmuxList),
+    #[structopt(about = "Send keys to pane")]
+    Send(TmuxSend),
+    #[structopt(external_subcommand)]
+    Other(Vec<String>),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TmuxList {
+    #[structopt(
+        short,
+        long,
+        value_name = "target",
+        help = "List only this session or window, list all if not given"
+    )]
+    pub target: Option<String>,
+    #[structopt(long, help = "Print as JSON")]
+    pub json: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TmuxSend {
+    #[structopt(short, long, help = "Send keys literally without key name lookup")]
+    pub literal: bool,
+    #[structopt(value_name = "target", help = "Target pane, e.g. main:1.0 or %3")]
+    pub target: String,
+    #[structopt(required = true, value_name = "keys", help = "Keys to send")]
+    pub keys: Vec<String>,
 }
 
 pub fn parse() -> CmdOpt {
     CmdOpt::from_args()
 }
+
+/// Expand socket name given by `-L`, `socket` of `config` or the default one, exit if it is invalid
+pub fn resolve_socket(cmd_opt: &mut CmdOpt, config: &Config) {
+    let template = cmd_opt
+        .socket_template
+        .as_deref()
+        .or(config.socket.as_deref())
+        .unwrap_or(DEFAULT_SOCKET);
+    cmd_opt.socket = socket::resolve(template).unwrap_or_else(|e| {
+        error!("{}", e);
+        exit(1);
+    });
+    if cmd_opt.socket != template {
+        debug!("Socket {} resolved to {}", template, cmd_opt.socket);
+    }
+}
+
+/// Socket of running server that subcommand talks to
+///
+/// Inside tmux without `-L`, this is the server running the command. Expanding the template
+/// could give another one, e.g. `{project}` differs when cwd of pane is in another project.
+pub fn resolve_server_socket(cmd_opt: &mut CmdOpt) -> nmk::Result<()> {
+    if cmd_opt.socket_template.is_none() {
+        if let Some(socket) = tmux::current_socket() {
+            debug!("Using socket {} of current tmux server", socket);
+            cmd_opt.socket = socket;
+            return Ok(());
+        }
+    }
+    let config = match NmkHome::locate() {
+        Some(nmk_home) => Config::load(nmk_home.path())?,
+        None => Config::default(),
+    };
+    resolve_socket(cmd_opt, &config);
+    Ok(())
+}
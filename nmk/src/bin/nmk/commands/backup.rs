@@ -1,11 +1,16 @@
-use std::path::PathBuf;
-
 use nmk::backup::backup_files;
+use nmk::config::Config;
 use nmk::home::NmkHome;
 
-pub fn backup() -> nmk::Result<()> {
-    let output_path = PathBuf::from("nmk-backup.tar");
+use crate::cmdline::Backup;
+
+pub fn backup(options: Backup) -> nmk::Result<()> {
     let nmk_home = NmkHome::locate().expect("failed to locate NMK_HOME");
-    backup_files(&nmk_home, &output_path)?;
+    let mut config = Config::load(nmk_home.path())?.backup;
+    if let Some(keep) = options.keep {
+        config.keep = keep;
+    }
+    let output_dir = options.output_dir.unwrap_or_else(|| config.output_dir());
+    backup_files(&nmk_home, &config, &output_dir)?;
     Ok(())
 }
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use log::debug;
+
+use nmk::clipboard::{self, ClipboardConfig};
+use nmk::config::Config;
+use nmk::consts::bin::TMUX;
+use nmk::home::NmkHome;
+
+use crate::cmdline::{Clipboard, ClipboardPaste};
+
+const PASTE_BUFFER: &str = "nmk-clipboard";
+
+pub fn clipboard(options: Clipboard) -> nmk::Result<()> {
+    // called from tmux bindings, missing NMK_HOME falls back to default config
+    let config = match NmkHome::locate() {
+        Some(nmk_home) => Config::load(nmk_home.path())?.clipboard,
+        None => ClipboardConfig::default(),
+    };
+    match options {
+        Clipboard::Copy => {
+            let data = clipboard::read_stdin()?;
+            let backend = clipboard::copy(&config, &data)?;
+            debug!("Copied {} bytes with {}", data.len(), backend.as_str());
+        }
+        Clipboard::Paste(opt) => paste(&config, opt)?,
+    }
+    Ok(())
+}
+
+fn paste(config: &ClipboardConfig, options: ClipboardPaste) -> nmk::Result<()> {
+    let data = clipboard::paste(config)?;
+    if !options.tmux {
+        io::stdout().write_all(&data)?;
+        return Ok(());
+    }
+    // tmux of the client that invoked the binding is found from TMUX
+    let mut child = Command::new(TMUX)
+        .args(["load-buffer", "-b", PASTE_BUFFER, "-"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&data)?;
+    }
+    if !child.wait()?.success() {
+        return Err(io::Error::other("tmux load-buffer failed").into());
+    }
+    Command::new(TMUX)
+        .args(["paste-buffer", "-d", "-b", PASTE_BUFFER])
+        .status()?;
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use std::io;
+
+use nmk::home::NmkHome;
+use nmk::shell_env;
+
+use crate::cmdline::Env;
+use crate::entrypoint::{exported, setup_environment};
+
+pub fn env(options: Env) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate dotfiles directory");
+    setup_environment(&nmk_home);
+    shell_env::write(&mut io::stdout(), options.shell, &exported())?;
+    Ok(())
+}
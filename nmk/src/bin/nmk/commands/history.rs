@@ -0,0 +1,79 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::exit;
+
+use log::{debug, error, info};
+
+use nmk::config::Config;
+use nmk::history::{self, Format};
+use nmk::home::NmkHome;
+use nmk::setup::write_atomic;
+
+use crate::cmdline::{History, HistoryMerge};
+
+pub fn history(options: History) -> nmk::Result<()> {
+    match options {
+        History::Merge(opt) => merge(opt),
+    }
+}
+
+fn merge(options: HistoryMerge) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate NMK_HOME");
+    let config = Config::load(nmk_home.path())?.history;
+    let mut sources = Vec::with_capacity(options.files.len() + 1);
+    for path in &options.files {
+        sources.push((fs::read(path)?, path));
+    }
+    let format = match options.format {
+        Some(format) => format,
+        None => detect_format(&sources),
+    };
+    debug!("History format: {:?}", format);
+    let output = options
+        .output
+        .unwrap_or_else(|| format.nmk_history_file(nmk_home.path()));
+    let current = if !options.no_current && output.exists() {
+        Some(fs::read(&output)?)
+    } else {
+        None
+    };
+
+    let mut patterns = options.exclude;
+    if options.filter_secrets {
+        patterns.extend(config.secret_patterns);
+    }
+    let patterns = history::compile_patterns(&patterns)?;
+
+    let entries = current
+        .iter()
+        .chain(sources.iter().map(|(contents, _)| contents))
+        .map(|contents| history::parse(format, contents))
+        .collect();
+    let merged = history::merge(entries, &patterns);
+    let contents = history::write(format, &merged);
+    if options.stdout {
+        io::stdout().write_all(&contents)?;
+    } else {
+        write_atomic(&output, &contents, 0o600)?;
+        info!("Merged {} entries into {}", merged.len(), output.display());
+    }
+    Ok(())
+}
+
+/// Format shared by all files, exit if they look different
+fn detect_format(sources: &[(Vec<u8>, &PathBuf)]) -> Format {
+    let detected: Vec<_> = sources
+        .iter()
+        .map(|(contents, path)| (Format::detect(path, contents), path))
+        .collect();
+    let format = detected[0].0;
+    if detected.iter().any(|(f, _)| *f != format) {
+        for (f, path) in &detected {
+            error!("{} looks like {:?} history", path.display(), f);
+        }
+        error!("History files are in different formats, pass --format to merge them anyway");
+        exit(1);
+    }
+    format
+}
@@ -0,0 +1,33 @@
+use std::io;
+use std::process::exit;
+
+use log::error;
+
+use nmk::config::Config;
+use nmk::home::NmkHome;
+use nmk::tmux::config::keymap;
+
+use crate::cmdline::{CmdOpt, Keys};
+use crate::terminal;
+use crate::tmux::{make_config_context, Tmux};
+
+/// Bindings come from the same context as rendered config, so commands like reload match
+pub fn keys(cmd_opt: &CmdOpt, options: Keys) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate NMK_HOME");
+    let config = Config::load(nmk_home.path())?;
+    let tmux = Tmux::new();
+    let capabilities = terminal::probe(cmd_opt.force_256_color);
+    let context = make_config_context(cmd_opt, &nmk_home, &capabilities, tmux.version, &config);
+    let keymap = keymap(&context, tmux.version);
+    if let Some(ref table) = options.table {
+        if !keymap.tables().contains(&table.as_str()) {
+            error!("No binding in key table {}", table);
+            exit(1);
+        }
+    }
+    match keymap.cheat_sheet(&mut io::stdout(), options.table.as_deref()) {
+        // output piped to pager or head that exits early
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
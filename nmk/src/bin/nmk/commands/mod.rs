@@ -1,5 +1,14 @@
 pub mod backup;
+pub mod clipboard;
 pub mod completion;
+pub mod env;
+pub mod history;
 pub mod info;
+pub mod keys;
+pub mod open;
+pub mod reload;
+pub mod session;
 pub mod setup;
+pub mod status_segment;
+pub mod theme;
 pub mod tmux;
@@ -0,0 +1,69 @@
+use std::env;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+
+use log::{debug, error};
+
+use nmk::consts::bin::TMUX;
+use nmk::home::NmkHome;
+use nmk::tmux::layout::{sanitize_session_name, Layout};
+
+use crate::cmdline::{CmdOpt, Open};
+use crate::entrypoint::{self, Workspace};
+use crate::tmux::{is_inside_server, Tmux};
+
+const PROJECT_LAYOUT: &str = ".nmk-layout.toml";
+
+/// Find layout file and its default session name
+fn find_layout(nmk_home: &NmkHome, layout: Option<&str>) -> Option<(PathBuf, String)> {
+    let file_stem = |p: &Path| {
+        p.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    match layout {
+        None => {
+            let cwd = env::current_dir().ok()?;
+            let path = cwd.join(PROJECT_LAYOUT);
+            let name = cwd.file_name()?.to_string_lossy().into_owned();
+            Some((path, name)).filter(|(p, _)| p.is_file())
+        }
+        Some(layout) => {
+            let path = PathBuf::from(layout);
+            if path.is_file() {
+                let name = file_stem(&path);
+                return Some((path, name));
+            }
+            let path = nmk_home.path().layouts().join(format!("{}.toml", layout));
+            Some((path, layout.to_string())).filter(|(p, _)| p.is_file())
+        }
+    }
+}
+
+pub fn open(cmd_opt: CmdOpt, options: Open) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate NMK_HOME");
+    let (path, name) = find_layout(&nmk_home, options.layout.as_deref()).unwrap_or_else(|| {
+        error!("Not found layout file");
+        exit(1);
+    });
+    debug!("Layout file: {}", path.display());
+    let layout = Layout::load(&path)?;
+    let session = sanitize_session_name(layout.session_name(&name));
+    if is_inside_server(&cmd_opt.socket) {
+        // Environment has been setup by entrypoint, create session in background then switch to it
+        let tmux = Tmux::new();
+        if !tmux.has_session(&cmd_opt.socket, &session) {
+            tmux.create_detached_session(&cmd_opt.socket, &session, &layout)?;
+        }
+        let mut cmd = Command::new(TMUX);
+        cmd.args(["-L", &cmd_opt.socket, "switch-client", "-t"])
+            .arg(format!("={}", session));
+        debug!("exec command: {:?}", cmd);
+        let err = cmd.exec();
+        panic!("exec {:?} fail with {:?}", cmd, err);
+    } else {
+        entrypoint::start(cmd_opt, Workspace::Layout(session, layout))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::env;
+use std::process::exit;
+
+use log::{debug, error};
+
+use nmk::consts::env::NMK_NESTED;
+use nmk::home::NmkHome;
+use nmk::setup::write_atomic;
+use nmk::tmux::config::{render_unbind_list, BINDINGS_OPTION};
+use nmk::tmux::theme::Theme;
+
+use crate::cmdline::CmdOpt;
+use crate::entrypoint::{prepare, render_config, set_env, validate_config};
+use crate::terminal;
+use crate::tmux::{self, create_nmk_tmp_dir, is_server_running, Tmux};
+
+/// Environment that config depends on, read from server so that reload from a key
+/// binding, where `TERM` is tmux's, renders the same config as start
+const SERVER_ENVIRONMENT: &[&str] = &["TERM", "COLORTERM", "TERM_PROGRAM", "SSH_CONNECTION"];
+
+/// Value of variable in global environment of server, `None` if it is unset
+fn server_environment(socket: &str, key: &str) -> Option<String> {
+    let output = tmux::output(socket, &["show-environment", "-g", key]).ok()?;
+    // `-KEY` means the variable is removed
+    output
+        .trim_end()
+        .strip_prefix(key)?
+        .strip_prefix('=')
+        .map(String::from)
+}
+
+/// Whether server was started inside another tmux
+///
+/// `TMUX` of outer server is removed before starting nested one, so entrypoint records
+/// the decision in `NMK_NESTED` instead.
+fn is_server_nested(socket: &str) -> bool {
+    server_environment(socket, NMK_NESTED).as_deref() == Some("1")
+}
+
+pub fn reload(cmd_opt: &CmdOpt) -> nmk::Result<()> {
+    let socket = &cmd_opt.socket;
+    if !is_server_running(socket) {
+        error!("tmux server on socket {} is not running", socket);
+        exit(1);
+    }
+    let nmk_home = NmkHome::locate().expect("failed to locate dotfiles directory");
+    let nmk_config = prepare(&nmk_home);
+    for key in SERVER_ENVIRONMENT {
+        match server_environment(socket, key) {
+            Some(value) => set_env(key, value),
+            None => env::remove_var(key),
+        }
+    }
+    let tmux = Tmux::new();
+    let mut capabilities = terminal::probe(cmd_opt.force_256_color);
+    capabilities.nested = is_server_nested(socket);
+    let buf = render_config(cmd_opt, &nmk_home, &nmk_config, &tmux, &capabilities)?;
+    let buf = validate_config(buf, nmk_config.validate);
+    let config = tmux.write_config_in_temp_dir(cmd_opt, &buf)?;
+
+    // bindings and colours of previous config are removed before sourcing new one
+    let previous = tmux::output(socket, &["show-option", "-gqv", BINDINGS_OPTION])?;
+    let mut script = Vec::new();
+    render_unbind_list(&mut script, &previous)?;
+    Theme::render_reset(&mut script)?;
+    let reload_file = create_nmk_tmp_dir()?.join(format!("reload-{}.conf", socket));
+    write_atomic(&reload_file, &script, 0o600)?;
+    debug!("Reloading {} into server {}", config.display(), socket);
+    let reload_path = reload_file.to_string_lossy();
+    let config_path = config.to_string_lossy();
+    let result = tmux::output(
+        socket,
+        &[
+            "source-file",
+            &reload_path,
+            ";",
+            "source-file",
+            &config_path,
+        ],
+    );
+    std::fs::remove_file(&reload_file)?;
+    result?;
+    tmux::output(socket, &["display-message", "nmk config reloaded"])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::{Command, Stdio};
+
+    use nmk::consts::bin::TMUX;
+
+    use super::*;
+
+    #[test]
+    fn test_is_server_nested() {
+        if which::which(TMUX).is_err() {
+            eprintln!("skipped test_is_server_nested: {} not found", TMUX);
+            return;
+        }
+        // separate sockets, a new server may fail while the killed one is shutting down
+        let check = |nested: &str| {
+            let socket = format!("nmk-reload-test-{}-{}", std::process::id(), nested);
+            let status = Command::new(TMUX)
+                .args(["-L", &socket, "-f", "/dev/null", "new-session", "-d", "cat"])
+                .env(NMK_NESTED, nested)
+                .env_remove("TMUX")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            let result = is_server_nested(&socket);
+            let _ = Command::new(TMUX)
+                .args(["-L", &socket, "kill-server"])
+                .stderr(Stdio::null())
+                .status();
+            // tmux leaves socket file behind
+            let _ = std::fs::remove_file(tmux::server_socket_path(&socket));
+            result
+        };
+        assert!(check("1"));
+        assert!(!check("0"));
+    }
+}
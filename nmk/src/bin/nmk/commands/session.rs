@@ -0,0 +1,116 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, info, warn};
+
+use nmk::config::Config;
+use nmk::datetime::DateTime;
+use nmk::home::NmkHome;
+use nmk::tmux::session::{self, foreground_command, PaneLine, Snapshot, LIST_PANES_FORMAT};
+
+use crate::cmdline::{CmdOpt, Session, SessionSave};
+use crate::entrypoint::{self, Workspace};
+use crate::tmux::{self, is_server_running, Tmux};
+
+pub fn session(cmd_opt: CmdOpt, options: Session) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate NMK_HOME");
+    let config = Config::load(nmk_home.path())?;
+    match options {
+        Session::Save(opt) => save(&cmd_opt, &nmk_home, &config, opt),
+        Session::Restore => restore(cmd_opt, &nmk_home, &config),
+    }
+}
+
+fn is_recently_saved(path: &Path, interval: Duration) -> bool {
+    path.metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .is_some_and(|elapsed| elapsed < interval)
+}
+
+fn capture_pane(socket: &str, pane: &PaneLine, dir: &Path) -> Option<PathBuf> {
+    let args = ["capture-pane", "-p", "-J", "-S", "-", "-t", pane.pane_id];
+    match tmux::output(socket, &args) {
+        Ok(contents) => {
+            let path = dir.join(pane.scrollback_name());
+            let contents = contents.trim_end();
+            fs::write(&path, contents).ok().map(|_| path)
+        }
+        Err(e) => {
+            warn!("Failed to capture pane {}: {}", pane.pane_id, e);
+            None
+        }
+    }
+}
+
+fn save(
+    cmd_opt: &CmdOpt,
+    nmk_home: &NmkHome,
+    config: &Config,
+    options: SessionSave,
+) -> nmk::Result<()> {
+    let socket = &cmd_opt.socket;
+    let path = session::snapshot_path(nmk_home.path(), socket);
+    if options.auto {
+        let interval = Duration::from_secs(config.session.autosave_interval * 60);
+        if !config.session.autosave || is_recently_saved(&path, interval) {
+            return Ok(());
+        }
+    }
+    let output = tmux::output(socket, &["list-panes", "-a", "-F", LIST_PANES_FORMAT])?;
+    let panes: Vec<_> = output.lines().flat_map(PaneLine::parse).collect();
+
+    let scrollback_dir = session::scrollback_dir(nmk_home.path(), socket);
+    let save_scrollback = options.scrollback || config.session.scrollback;
+    if scrollback_dir.exists() {
+        fs::remove_dir_all(&scrollback_dir)?;
+    }
+    if save_scrollback {
+        fs::create_dir_all(&scrollback_dir)?;
+    }
+    let snapshot = Snapshot::from_panes(
+        DateTime::now().to_string(),
+        &panes,
+        |p| foreground_command(p.pane_pid).or_else(|| Some(p.current_command.to_string())),
+        |p| {
+            if save_scrollback {
+                capture_pane(socket, p, &scrollback_dir)
+            } else {
+                None
+            }
+        },
+    );
+    snapshot.save(&path)?;
+    if options.auto {
+        debug!("Saved sessions to {}", path.display());
+    } else {
+        info!(
+            "Saved {} sessions to {}",
+            snapshot.sessions.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn restore(cmd_opt: CmdOpt, nmk_home: &NmkHome, config: &Config) -> nmk::Result<()> {
+    let path = session::snapshot_path(nmk_home.path(), &cmd_opt.socket);
+    let layouts = Snapshot::load(&path)?.to_layouts(&config.session.restore_commands);
+    if is_server_running(&cmd_opt.socket) {
+        let tmux = Tmux::new();
+        for layout in &layouts {
+            let name = layout.session_name("0");
+            if tmux.has_session(&cmd_opt.socket, name) {
+                info!("Session {} is already running", name);
+            } else {
+                tmux.create_detached_session(&cmd_opt.socket, name, layout)?;
+                info!("Restored session {}", name);
+            }
+        }
+    } else {
+        entrypoint::start(cmd_opt, Workspace::Restore(layouts))?;
+    }
+    Ok(())
+}
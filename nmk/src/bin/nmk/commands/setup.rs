@@ -6,9 +6,11 @@ use std::{fs, io};
 use log::{debug, info};
 use xz2::read::XzDecoder;
 
+use nmk::backup::snapshot_before_update;
+use nmk::config::Config;
 use nmk::home::{NmkHome, NmkPath};
 use nmk::setup::install_busy;
-use nmk::{dotfiles, vendor};
+use nmk::{dotfiles, terminfo, vendor};
 
 use crate::cmdline::Setup;
 
@@ -22,7 +24,7 @@ pub fn setup(options: Setup) -> nmk::Result<()> {
     // dotfiles must be installed first to get /bin for entrypoint
     if let Some(source) = options.dotfiles {
         assert!(source.exists(), "Not found dot files source");
-        setup_dotfiles(&source, nmk_path)?;
+        setup_dotfiles(&source, &nmk_home)?;
         // remove old metadata if exists
         let metadata = nmk_path.dotfiles_meta();
         if metadata.exists() {
@@ -42,15 +44,26 @@ pub fn setup(options: Setup) -> nmk::Result<()> {
         assert!(source.exists(), "Not found vendor source");
         extract_vendor(&source, nmk_path)?;
     }
+    if options.terminfo {
+        let dir = nmk_path.terminfo();
+        for name in terminfo::install_bundled(&dir)? {
+            info!("Installed terminfo {} into {}", name, dir.display());
+        }
+    }
     Ok(())
 }
 
-fn setup_dotfiles(source: &Path, nmk_path: &NmkPath) -> nmk::Result<()> {
+fn setup_dotfiles(source: &Path, nmk_home: &NmkHome) -> nmk::Result<()> {
+    let nmk_path = nmk_home.path();
     let dotfiles_dir = nmk_path.as_path();
     if !dotfiles_dir.exists() {
         fs::create_dir_all(dotfiles_dir)?;
     }
     if nmk_path.dotfiles_file_list().exists() {
+        let config = Config::load(nmk_path)?.backup;
+        if let Some(path) = snapshot_before_update(nmk_home, &config)? {
+            info!("Snapshot of local files written to {}", path.display());
+        }
         dotfiles::uninstall(nmk_path)?;
     }
     let data = BufReader::new(File::open(source)?);
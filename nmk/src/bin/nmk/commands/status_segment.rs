@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use nmk::config::Config;
+use nmk::consts::bin::TMUX;
+use nmk::home::NmkHome;
+use nmk::tmux::status;
+
+use crate::cmdline::StatusSegment;
+use crate::tmux::create_nmk_tmp_dir;
+
+pub fn status_segment(options: StatusSegment) -> nmk::Result<()> {
+    // tmux shows stderr in status line, so missing NMK_HOME falls back to default config
+    let config = match NmkHome::locate() {
+        Some(nmk_home) => Config::load(nmk_home.path())?,
+        None => Config::default(),
+    };
+    let cache_dir = create_nmk_tmp_dir()?.join("status");
+    let ttl = Duration::from_secs(config.status.cache_seconds);
+    let path = match options.path {
+        Some(path) => Some(path),
+        None if options.segment.needs_path() => pane_current_path(),
+        None => None,
+    };
+    let text = status::render_cached(&cache_dir, ttl, options.segment, path.as_deref());
+    println!("{}", text);
+    Ok(())
+}
+
+/// Ask server running this job through `TMUX` in its environment
+fn pane_current_path() -> Option<PathBuf> {
+    let output = Command::new(TMUX)
+        .args(["display-message", "-p", "#{pane_current_path}"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let path = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    if output.status.success() && !path.is_empty() {
+        Some(PathBuf::from(path))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs;
+use std::process::exit;
+
+use log::{error, info};
+
+use nmk::config::{set_top_level_string, Config};
+use nmk::home::{NmkHome, NmkPath};
+use nmk::setup::write_atomic;
+use nmk::tmux::theme::{self, default_name, ColorDepth, Theme, ThemeSource};
+
+use crate::cmdline::{CmdOpt, ThemeCommand, ThemePreview, ThemeSet};
+use crate::commands::reload::reload;
+use crate::terminal;
+use crate::tmux::is_server_running;
+
+pub fn theme(cmd_opt: CmdOpt, options: ThemeCommand) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate NMK_HOME");
+    let config = Config::load(nmk_home.path())?;
+    match options {
+        ThemeCommand::List => list(&cmd_opt, &nmk_home, &config),
+        ThemeCommand::Preview(opt) => preview(&nmk_home, &config, opt),
+        ThemeCommand::Set(opt) => set(&cmd_opt, &nmk_home, opt),
+    }
+}
+
+fn color_depth(cmd_opt: &CmdOpt) -> ColorDepth {
+    terminal::probe(cmd_opt.force_256_color).color_depth
+}
+
+fn load_or_exit(nmk_path: &NmkPath, name: &str) -> nmk::Result<Theme> {
+    match Theme::load(nmk_path, name)? {
+        Some(theme) => Ok(theme),
+        None => {
+            error!("Theme {} not found, see nmk theme list", name);
+            exit(1);
+        }
+    }
+}
+
+fn list(cmd_opt: &CmdOpt, nmk_home: &NmkHome, config: &Config) -> nmk::Result<()> {
+    let current = match config.theme {
+        Some(ref name) => name.as_str(),
+        None => default_name(color_depth(cmd_opt)),
+    };
+    for (name, source) in theme::list(nmk_home.path()) {
+        let marker = if name == current { "*" } else { " " };
+        let source = match source {
+            ThemeSource::Builtin => "builtin",
+            ThemeSource::User => "user",
+        };
+        let description = Theme::load(nmk_home.path(), &name)
+            .ok()
+            .flatten()
+            .and_then(|t| t.description)
+            .unwrap_or_default();
+        println!("{} {:<12} {:<8} {}", marker, name, source, description);
+    }
+    Ok(())
+}
+
+fn preview(nmk_home: &NmkHome, config: &Config, options: ThemePreview) -> nmk::Result<()> {
+    let depth = if options.true_color {
+        ColorDepth::TrueColor
+    } else {
+        terminal::probe(false).color_depth
+    };
+    let name = options
+        .name
+        .as_deref()
+        .or(config.theme.as_deref())
+        .unwrap_or_else(|| default_name(depth));
+    let theme = load_or_exit(nmk_home.path(), name)?;
+    println!("{} ({:?})", name, depth);
+    print!("{}", theme.preview(depth));
+    Ok(())
+}
+
+fn set(cmd_opt: &CmdOpt, nmk_home: &NmkHome, options: ThemeSet) -> nmk::Result<()> {
+    load_or_exit(nmk_home.path(), &options.name)?;
+    let path = nmk_home.path().config();
+    let contents = if path.exists() {
+        fs::read_to_string(&path)?
+    } else {
+        String::new()
+    };
+    let contents = set_top_level_string(&contents, "theme", &options.name);
+    write_atomic(&path, contents.as_bytes(), 0o644)?;
+    info!("Set theme to {} in {}", options.name, path.display());
+
+    // full reload renders theme with colour depth of terminal that started the server
+    if is_server_running(&cmd_opt.socket) {
+        reload(cmd_opt)?;
+        info!("Applied theme to running tmux server");
+    }
+    Ok(())
+}
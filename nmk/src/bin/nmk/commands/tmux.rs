@@ -2,20 +2,103 @@ use std::os::unix::process::CommandExt;
 use std::process::{exit, Command};
 
 use log::{debug, error};
+use serde::Serialize;
 
 use nmk::consts::bin::TMUX;
 use nmk::consts::env::NMK_START_MODE;
+use nmk::tmux::query::{PaneInfo, Query, SessionInfo, WindowInfo};
 
-use crate::cmdline::{CmdOpt, Tmux};
+use crate::cmdline::{CmdOpt, Tmux, TmuxCommand, TmuxList, TmuxSend};
 use crate::terminal;
+use crate::tmux::{self, is_server_running, server_socket_path};
 
-pub fn command(cmd_opt: &CmdOpt, options: Tmux) -> ! {
+pub fn command(cmd_opt: &CmdOpt, options: Tmux) -> nmk::Result<()> {
+    let socket = &cmd_opt.socket;
+    let cmd = match options.cmd {
+        Some(TmuxCommand::Other(args)) => exec(cmd_opt, &args),
+        None => exec(cmd_opt, &[]),
+        Some(cmd) => cmd,
+    };
+    if !is_server_running(socket) {
+        error!(
+            "No tmux server is running on {}",
+            server_socket_path(socket).display()
+        );
+        exit(1);
+    }
+    match cmd {
+        TmuxCommand::Ls(opt) => list::<SessionInfo, _>(socket, opt, |s| {
+            let attached = if s.attached > 0 { " (attached)" } else { "" };
+            format!("{}: {} windows{}", s.name, s.windows, attached)
+        }),
+        TmuxCommand::Windows(opt) => list::<WindowInfo, _>(socket, opt, |w| {
+            let active = if w.active { " (active)" } else { "" };
+            format!(
+                "{}:{}: {} ({} panes){}",
+                w.session, w.index, w.name, w.panes, active
+            )
+        }),
+        TmuxCommand::Panes(opt) => list::<PaneInfo, _>(socket, opt, |p| {
+            let active = if p.active { " (active)" } else { "" };
+            format!(
+                "{}:{}.{}: {} {} [{}x{}] {}{}",
+                p.session,
+                p.window_index,
+                p.index,
+                p.current_command,
+                p.current_path.display(),
+                p.width,
+                p.height,
+                p.id,
+                active
+            )
+        }),
+        TmuxCommand::Send(opt) => send(socket, opt),
+        TmuxCommand::Other(_) => unreachable!(),
+    }
+}
+
+/// Run `list-*` command of `T` and print each item with `display` or as JSON array
+fn list<T, F>(socket: &str, options: TmuxList, display: F) -> nmk::Result<()>
+where
+    T: Query + Serialize,
+    F: Fn(&T) -> String,
+{
+    let mut args = vec![T::COMMAND, "-F", T::FORMAT];
+    match options.target {
+        Some(ref target) => args.extend(["-t", target]),
+        // list-sessions lists all sessions without -a
+        None if T::COMMAND != SessionInfo::COMMAND => args.push("-a"),
+        None => {}
+    }
+    let items = T::parse_all(&tmux::output(socket, &args)?);
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&items)?);
+    } else {
+        for item in &items {
+            println!("{}", display(item));
+        }
+    }
+    Ok(())
+}
+
+fn send(socket: &str, options: TmuxSend) -> nmk::Result<()> {
+    let mut args = vec!["send-keys", "-t", &options.target];
+    if options.literal {
+        args.push("-l");
+    }
+    args.extend(options.keys.iter().map(String::as_str));
+    tmux::output(socket, &args)?;
+    Ok(())
+}
+
+fn exec(cmd_opt: &CmdOpt, args: &[String]) -> ! {
     if std::env::var(NMK_START_MODE).is_err() {
         error!("nmk tmux subcommand need shell started from entrypoint");
         exit(1);
     }
     let mut cmd = Command::new(TMUX);
-    cmd.args(&["-L", &cmd_opt.socket]);
+    cmd.args(["-L", &cmd_opt.socket]);
     let support_256_color = cmd_opt.force_256_color || terminal::support_256_color();
     if support_256_color {
         cmd.arg("-2");
@@ -23,7 +106,7 @@ pub fn command(cmd_opt: &CmdOpt, options: Tmux) -> ! {
     if cmd_opt.unicode {
         cmd.arg("-u");
     }
-    cmd.args(options.args.iter());
+    cmd.args(args);
     debug!("exec command: {:?}", cmd);
     let err = cmd.exec();
     panic!("exec {:?} fail with {:?}", cmd, err);
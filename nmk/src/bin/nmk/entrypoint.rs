@@ -1,28 +1,61 @@
-use std::array::IntoIter;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fs::File;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::Path;
+use std::process::exit;
+use std::sync::Mutex;
 use std::{env, io};
 
-use log::debug;
+use log::{debug, error, warn};
+use once_cell::sync::Lazy;
 
+use nmk::config::Config;
 use nmk::consts::env::{
-    EDITOR, LD_LIBRARY_PATH, NMK_HOME, NMK_START_MODE, NMK_TMUX_VERSION, PATH, VIMINIT, ZDOTDIR,
+    EDITOR, LD_LIBRARY_PATH, NMK_HOME, NMK_NESTED, NMK_START_MODE, NMK_TMUX_VERSION, PATH,
+    TERMINFO_DIRS, VIMINIT, ZDOTDIR,
 };
 use nmk::home::NmkHome;
 use nmk::human_time::{seconds_since_build, HumanTime};
+use nmk::probe_cache;
+use nmk::terminfo;
+use nmk::timing;
+use nmk::tmux::layout::Layout;
+use nmk::tmux::profile::Profile;
+use nmk::tmux::query::{Query, SessionInfo};
+use nmk::tmux::session::{self, Snapshot};
+use nmk::tmux::socket;
+use nmk::tmux::validate::{self, ValidateMode};
 
-use crate::cmdline::CmdOpt;
+use crate::cmdline::{self, CmdOpt, Render};
 use crate::path_vec::PathVec;
-use crate::terminal;
-use crate::tmux::{make_config_context, Tmux};
+use crate::picker::{self, Choice};
+use crate::terminal::{self, Capabilities};
+use crate::tmux::{is_inside_server, is_server_running, make_config_context, Tmux};
+use crate::utils::print_usage_time;
+
+/// Variables exported by [`set_env`] in order, printed by `nmk env`
+static EXPORTED: Lazy<Mutex<Vec<(String, OsString)>>> = Lazy::new(Default::default);
 
 pub fn set_env<K: AsRef<str>, V: AsRef<OsStr>>(key: K, value: V) {
     let key = key.as_ref();
     let value = value.as_ref();
     env::set_var(key, value);
     debug!("export {}={:?}", key, value);
+    let mut exported = EXPORTED.lock().unwrap();
+    match exported.iter_mut().find(|(k, _)| k == key) {
+        Some(entry) => entry.1 = value.to_owned(),
+        None => exported.push((key.to_string(), value.to_owned())),
+    }
+}
+
+/// Variables exported so far, a variable exported twice keeps its first position
+pub fn exported() -> Vec<(String, String)> {
+    EXPORTED
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.to_string_lossy().into_owned()))
+        .collect()
 }
 
 fn setup_environment_variable(nmk_home: &NmkHome) {
@@ -33,6 +66,16 @@ fn setup_environment_variable(nmk_home: &NmkHome) {
     let vim_dir = nmk_home.path().vim();
     set_env(VIMINIT, build_vim_init(&vim_dir));
     setup_preferred_editor();
+
+    // Entries installed by nmk setup --terminfo
+    let terminfo_dir = nmk_home.path().terminfo();
+    if terminfo_dir.is_dir() {
+        let current = env::var(TERMINFO_DIRS).ok();
+        set_env(
+            TERMINFO_DIRS,
+            terminfo::prepend_terminfo_dirs(&terminfo_dir, current.as_deref()),
+        );
+    }
 }
 
 fn build_vim_init(vim_dir: &Path) -> String {
@@ -46,12 +89,14 @@ fn build_vim_init(vim_dir: &Path) -> String {
 /// - otherwise, check hard coded list of preferred editor and set to the one that exist
 fn setup_preferred_editor() {
     if let Some(editor) = env::var_os(EDITOR) {
-        if which::which(&editor).is_err() {
+        if probe_cache::which(&editor.to_string_lossy()).is_none() {
             log::warn!("Invalid {} or value does not exist: {:?}", EDITOR, editor)
         }
     } else {
         let preferred_editor = ["nvim", "vim"];
-        if let Some(ed) = IntoIter::new(preferred_editor).find(|bin| which::which(bin).is_ok()) {
+        if let Some(ed) =
+            IntoIterator::into_iter(preferred_editor).find(|bin| probe_cache::which(bin).is_some())
+        {
             debug!("Using {} as preferred editor", ed);
             set_env(EDITOR, ed);
         }
@@ -68,7 +113,7 @@ fn setup_shell_search_path(nmk_home: &NmkHome) {
     ];
     let search_path = env::var_os(PATH).unwrap_or_else(|| panic!("{} doesn't exist", PATH));
     let mut search_path = PathVec::from(search_path);
-    search_path = IntoIter::new(nmk_search_path)
+    search_path = IntoIterator::into_iter(nmk_search_path)
         .filter(|p| p.exists())
         .chain(search_path)
         .collect();
@@ -115,19 +160,214 @@ fn check_for_update_suggest() {
     }
 }
 
+/// What tmux should attach to
+pub enum Workspace {
+    /// Attach to default session or create it, ask which one when there are several
+    Default,
+    /// Attach to named session or create it
+    Attach(String),
+    /// Create a new session named by tmux
+    New,
+    /// Attach to named session or create it from layout
+    Layout(String, Layout),
+    /// Recreate saved sessions then attach to the first one, tmux server must not be running
+    Restore(Vec<Layout>),
+}
+
+fn load_config(nmk_home: &NmkHome) -> Config {
+    Config::load(nmk_home.path()).unwrap_or_else(|e| {
+        error!("Failed to load config: {}", e);
+        exit(1);
+    })
+}
+
+fn find_profile(config: &mut Config, nmk_home: &NmkHome, name: &str) -> Profile {
+    config.profiles.remove(name).unwrap_or_else(|| {
+        error!(
+            "Not found profile {} in {}",
+            name,
+            nmk_home.path().config().display()
+        );
+        exit(1);
+    })
+}
+
+/// Socket name of profile, it may be a template like `-L`
+fn profile_socket(name: &str, template: &str) -> String {
+    socket::resolve(template).unwrap_or_else(|e| {
+        error!("Invalid socket of profile {}: {}", name, e);
+        exit(1);
+    })
+}
+
+/// Saved sessions to restore if tmux server on `socket` is not running
+fn find_saved_sessions(nmk_home: &NmkHome, config: &Config, socket: &str) -> Option<Vec<Layout>> {
+    if !config.session.restore_on_start || is_server_running(socket) {
+        return None;
+    }
+    let path = session::snapshot_path(nmk_home.path(), socket);
+    if !path.exists() {
+        return None;
+    }
+    match Snapshot::load(&path) {
+        Ok(snapshot) => {
+            debug!("Restoring sessions from {}", path.display());
+            Some(snapshot.to_layouts(&config.session.restore_commands))
+                .filter(|layouts| !layouts.is_empty())
+        }
+        Err(e) => {
+            warn!("Ignored saved sessions {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Export environment for vendored tmux and zsh
+pub fn setup_environment(nmk_home: &NmkHome) {
+    setup_shell_library_path(nmk_home);
+    setup_shell_search_path(nmk_home);
+    setup_environment_variable(nmk_home);
+    crate::zsh::init(nmk_home);
+}
+
+/// Export environment for vendored tmux and zsh then load nmk.toml
+pub fn prepare(nmk_home: &NmkHome) -> Config {
+    debug!("dotfiles directory: {}", nmk_home);
+    timing::measure("environment", || setup_environment(nmk_home));
+    timing::measure("load config", || load_config(nmk_home))
+}
+
+pub fn render_config(
+    cmd_opt: &CmdOpt,
+    nmk_home: &NmkHome,
+    nmk_config: &Config,
+    tmux: &Tmux,
+    capabilities: &Capabilities,
+) -> io::Result<Vec<u8>> {
+    let context = timing::measure("context", || {
+        make_config_context(cmd_opt, nmk_home, capabilities, tmux.version, nmk_config)
+    });
+    let mut buf = Vec::with_capacity(8192);
+    timing::measure("render", || {
+        nmk::tmux::config::render(&mut buf, &context, tmux.version)
+    })?;
+    debug!(
+        "tmux configuration length: {}, capacity: {}, remaining bytes before re-alloc: {}",
+        buf.len(),
+        buf.capacity(),
+        buf.capacity() - buf.len(),
+    );
+    Ok(buf)
+}
+
+/// Check config with throwaway tmux server, rejected lines are logged or commented out
+pub fn validate_config(buf: Vec<u8>, mode: ValidateMode) -> Vec<u8> {
+    if mode == ValidateMode::Off {
+        return buf;
+    }
+    let text = String::from_utf8_lossy(&buf).into_owned();
+    let problems = match validate::validate(&text) {
+        Ok(problems) => problems,
+        Err(e) => {
+            warn!("Failed to validate tmux config: {}", e);
+            return buf;
+        }
+    };
+    for problem in &problems {
+        warn!("tmux rejected config {}", problem);
+    }
+    if mode == ValidateMode::Strip && !problems.is_empty() {
+        validate::strip(&text, &problems).into_bytes()
+    } else {
+        buf
+    }
+}
+
+/// Print tmux config rendered with environment of entrypoint
+pub fn render(mut cmd_opt: CmdOpt, options: Render) -> nmk::Result<()> {
+    let nmk_home = NmkHome::locate().expect("failed to locate dotfiles directory");
+    let mut nmk_config = prepare(&nmk_home);
+    cmdline::resolve_socket(&mut cmd_opt, &nmk_config);
+    if let Some(ref name) = cmd_opt.profile {
+        if let Some(ref template) = find_profile(&mut nmk_config, &nmk_home, name).socket {
+            cmd_opt.socket = profile_socket(name, template);
+        }
+    }
+    let tmux = Tmux::new();
+    let capabilities = terminal::probe(cmd_opt.force_256_color);
+    let buf = render_config(&cmd_opt, &nmk_home, &nmk_config, &tmux, &capabilities)?;
+    if !options.check && !options.strip {
+        io::stdout().write_all(&buf)?;
+        return Ok(());
+    }
+    let text = String::from_utf8_lossy(&buf).into_owned();
+    let problems = validate::validate(&text)?;
+    if options.strip {
+        for problem in &problems {
+            warn!("tmux rejected config {}", problem);
+        }
+        print!("{}", validate::strip(&text, &problems));
+    } else if problems.is_empty() {
+        println!("tmux {} accepts rendered config", tmux.version);
+    } else {
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        error!(
+            "tmux {} rejected {} lines of rendered config",
+            tmux.version,
+            problems.len()
+        );
+        exit(1);
+    }
+    Ok(())
+}
+
 pub fn main(cmd_opt: CmdOpt) -> io::Result<()> {
+    let workspace = match cmd_opt.attach {
+        Some(ref name) => Workspace::Attach(name.clone()),
+        None if cmd_opt.new => Workspace::New,
+        None => Workspace::Default,
+    };
+    start(cmd_opt, workspace)
+}
+
+/// Ask which session to attach when stdin is a terminal and server has several sessions
+fn pick_session(tmux: &Tmux, socket: &str) -> io::Result<Workspace> {
+    if !io::stdin().is_terminal() || !is_server_running(socket) {
+        return Ok(Workspace::Default);
+    }
+    let output = tmux.output(socket, &[SessionInfo::COMMAND, "-F", SessionInfo::FORMAT])?;
+    let sessions = SessionInfo::parse_all(&output);
+    if sessions.len() <= 1 {
+        return Ok(Workspace::Default);
+    }
+    match picker::choose(socket, &sessions)? {
+        Choice::Attach(name) => Ok(Workspace::Attach(name)),
+        Choice::New => Ok(Workspace::New),
+        Choice::Quit => exit(0),
+    }
+}
+
+/// Setup environment then start zsh or tmux attached to `workspace`
+pub fn start(mut cmd_opt: CmdOpt, mut workspace: Workspace) -> io::Result<()> {
     if cmd_opt.motd {
         display_message_of_the_day()?;
         check_for_update_suggest()
     }
 
-    let nmk_home = NmkHome::locate().expect("failed to locate dotfiles directory");
-    debug!("dotfiles directory: {}", nmk_home);
-
-    setup_shell_library_path(&nmk_home);
-    setup_shell_search_path(&nmk_home);
-    setup_environment_variable(&nmk_home);
-    crate::zsh::init(&nmk_home);
+    let nmk_home = timing::measure("locate home", NmkHome::locate)
+        .expect("failed to locate dotfiles directory");
+    let mut nmk_config = timing::measure("prepare", || prepare(&nmk_home));
+    cmdline::resolve_socket(&mut cmd_opt, &nmk_config);
+    if let Some(ref name) = cmd_opt.profile {
+        let profile = find_profile(&mut nmk_config, &nmk_home, name);
+        if let Some(ref template) = profile.socket {
+            cmd_opt.socket = profile_socket(name, template);
+        }
+        let session = profile.layout.session_name(name).to_string();
+        workspace = Workspace::Layout(session, profile.layout);
+    }
     {
         let start_mode = if cmd_opt.login { "login" } else { "tmux" };
         // Environment variables necessary to use vendored tmux and zsh have been set.
@@ -137,38 +377,86 @@ pub fn main(cmd_opt: CmdOpt) -> io::Result<()> {
     if cmd_opt.login {
         crate::zsh::exec_login_shell(&cmd_opt);
     } else {
-        let tmux = Tmux::new();
+        let tmux = timing::measure("tmux version", Tmux::new);
+        let tmux = if cmd_opt.render {
+            tmux
+        } else {
+            timing::measure("server probe", || {
+                crate::server::recover(&cmd_opt.socket, tmux)
+            })?
+        };
         debug!("tmux path = {}", tmux.bin.display());
         debug!("tmux version = {}", tmux.version);
         set_env(NMK_TMUX_VERSION, tmux.version.as_str());
-        let support_256_color = cmd_opt.force_256_color || terminal::support_256_color();
+        let capabilities = timing::measure("terminal probe", || {
+            terminal::probe(cmd_opt.force_256_color)
+        });
+        // new server keeps it in global environment, reload cannot probe it from inside
+        set_env(NMK_NESTED, if capabilities.nested { "1" } else { "0" });
         let tmp_config;
         let config = if let Some(ref conf) = cmd_opt.tmux_conf {
             conf
         } else {
-            let context = make_config_context(&cmd_opt, support_256_color);
-            let mut buf = Vec::with_capacity(8192);
-            nmk::tmux::config::render(&mut buf, &context, tmux.version)?;
-            debug!(
-                "tmux configuration length: {}, capacity: {}, remaining bytes before re-alloc: {}",
-                buf.len(),
-                buf.capacity(),
-                buf.capacity() - buf.len(),
-            );
+            let buf = timing::measure("config", || {
+                render_config(&cmd_opt, &nmk_home, &nmk_config, &tmux, &capabilities)
+            })?;
             if cmd_opt.render {
-                return io::stdout().write_all(&buf);
+                io::stdout().write_all(&buf)?;
+                print_usage_time(&cmd_opt);
+                return Ok(());
             } else {
-                tmp_config = tmux.write_config_in_temp_dir(&cmd_opt, &buf)?;
+                let buf = timing::measure("validate", || validate_config(buf, nmk_config.validate));
+                tmp_config = timing::measure("write config", || {
+                    tmux.write_config_in_temp_dir(&cmd_opt, &buf)
+                })?;
                 &tmp_config
             }
         };
-        tmux.exec(&cmd_opt, config, support_256_color);
+        if env::var_os("TMUX").is_some() && !is_inside_server(&cmd_opt.socket) {
+            // tmux refuses to nest unless TMUX is unset, nesting a different server is fine
+            env::remove_var("TMUX");
+        }
+        if matches!(workspace, Workspace::Default) {
+            let saved = timing::measure("saved sessions", || {
+                find_saved_sessions(&nmk_home, &nmk_config, &cmd_opt.socket)
+            });
+            if let Some(layouts) = saved {
+                workspace = Workspace::Restore(layouts);
+            } else {
+                workspace = pick_session(&tmux, &cmd_opt.socket)?;
+            }
+        }
+        let session_args = match workspace {
+            Workspace::Default => tmux.default_session_args(),
+            Workspace::Attach(ref session) => tmux.attach_session_args(session),
+            Workspace::New => tmux.new_session_args(),
+            Workspace::Layout(ref session, ref layout) => {
+                tmux.layout_session_args(&cmd_opt.socket, session, layout)
+            }
+            Workspace::Restore(ref layouts) => tmux.restore_session_args(layouts),
+        };
+        tmux.exec(
+            &cmd_opt,
+            config,
+            capabilities.support_256_color(),
+            &session_args,
+        );
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
     use std::path::PathBuf;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use structopt::StructOpt;
+
+    use nmk::consts::bin::{TMUX, ZSH};
+    use nmk::tmux::theme::ColorDepth;
+    use nmk::tmux::version::Version;
 
     use super::*;
 
@@ -182,4 +470,70 @@ mod tests {
         let actual = build_vim_init(&vim_dir);
         assert_eq!(actual, "source '/home/user with space/.nmk/vim/init.vim'");
     }
+
+    /// Set in environment of child process that runs [`startup_bench_child`]
+    const BENCH_CHILD: &str = "NMK_STARTUP_BENCH_CHILD";
+
+    /// Time phases run on every login, run with `cargo test --release -- --ignored bench`
+    ///
+    /// Phases mutate process environment and global timing recorder, so they run in a
+    /// child test process. tmux version, terminal and server probes are stubbed, they
+    /// depend on the machine.
+    #[test]
+    #[ignore]
+    fn bench_startup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let zsh = dir.join("bin").join(ZSH);
+        fs::create_dir_all(zsh.parent().unwrap()).unwrap();
+        fs::write(&zsh, "").unwrap();
+        fs::set_permissions(&zsh, fs::Permissions::from_mode(0o755)).unwrap();
+        let module = module_path!().split_once("::").unwrap().1;
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--ignored", "--exact", "--nocapture"])
+            .arg(format!("{}::startup_bench_child", module))
+            .env(BENCH_CHILD, "1")
+            .env(NMK_HOME, dir)
+            .output()
+            .unwrap();
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        assert!(output.status.success());
+    }
+
+    #[test]
+    #[ignore]
+    fn startup_bench_child() {
+        const RUNS: u32 = 10;
+        if env::var_os(BENCH_CHILD).is_none() {
+            return;
+        }
+        let cmd_opt = CmdOpt::from_iter(["nmk"]);
+        let tmux = Tmux {
+            bin: PathBuf::from(TMUX),
+            version: Version::V32a,
+        };
+        let capabilities = Capabilities {
+            color_depth: ColorDepth::TrueColor,
+            term: Some(String::from("xterm-256color")),
+            nested: false,
+        };
+        timing::measure("startup benchmark", || {
+            for _ in 0..RUNS {
+                let nmk_home = timing::measure("locate home", NmkHome::locate).unwrap();
+                let config = timing::measure("prepare", || prepare(&nmk_home));
+                timing::measure("config", || {
+                    render_config(&cmd_opt, &nmk_home, &config, &tmux, &capabilities)
+                })
+                .unwrap();
+            }
+        });
+        let phases = timing::phases();
+        let total = phases[0].duration;
+        timing::write_text(&mut io::stderr(), &phases, total).unwrap();
+        let average = total / RUNS;
+        eprintln!("startup took {:?} on average", average);
+        if !cfg!(debug_assertions) {
+            assert!(average < Duration::from_millis(20));
+        }
+    }
 }
@@ -3,6 +3,8 @@ mod commands;
 mod entrypoint;
 mod logging;
 mod path_vec;
+mod picker;
+mod server;
 mod terminal;
 mod tmux;
 mod utils;
@@ -12,15 +14,29 @@ mod zsh;
 fn main() -> nmk::Result<()> {
     let mut cmd_opt = cmdline::parse();
     logging::setup(cmd_opt.verbosity);
+    tmux::init_probe_cache();
     log::debug!("Command line options: {:#?}", cmd_opt);
     if let Some(cmd) = cmd_opt.cmd.take() {
+        if cmd.needs_socket() {
+            cmdline::resolve_server_socket(&mut cmd_opt)?;
+        }
         use cmdline::SubCommand::*;
         match cmd {
-            Backup => commands::backup::backup()?,
+            Backup(opt) => commands::backup::backup(opt)?,
+            Clipboard(opt) => commands::clipboard::clipboard(opt)?,
             Completions(c) => commands::completion::generate_completion(c),
+            Env(opt) => commands::env::env(opt)?,
+            History(opt) => commands::history::history(opt)?,
             Info => commands::info::print_info()?,
+            Keys(opt) => commands::keys::keys(&cmd_opt, opt)?,
+            Open(opt) => commands::open::open(cmd_opt, opt)?,
+            Reload => commands::reload::reload(&cmd_opt)?,
+            Render(opt) => entrypoint::render(cmd_opt, opt)?,
+            Session(opt) => commands::session::session(cmd_opt, opt)?,
             Setup(v) => commands::setup::setup(v)?,
-            Tmux(opt) => commands::tmux::command(&cmd_opt, opt),
+            StatusSegment(opt) => commands::status_segment::status_segment(opt)?,
+            Theme(opt) => commands::theme::theme(cmd_opt, opt)?,
+            Tmux(opt) => commands::tmux::command(&cmd_opt, opt)?,
         }
     } else {
         entrypoint::main(cmd_opt)?;
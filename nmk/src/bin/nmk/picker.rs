@@ -0,0 +1,136 @@
+use std::io::{self, BufRead, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nmk::human_time::HumanTime;
+use nmk::tmux::query::SessionInfo;
+
+/// Session chosen from the picker
+#[derive(Debug, Eq, PartialEq)]
+pub enum Choice {
+    Attach(String),
+    New,
+    Quit,
+}
+
+fn plural(n: u32, word: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, word)
+    } else {
+        format!("{} {}s", n, word)
+    }
+}
+
+/// One line of the picker describing `session`, `now` is seconds since unix epoch
+pub fn describe(session: &SessionInfo, now: u64) -> String {
+    let attached = if session.attached == 0 {
+        "detached".to_string()
+    } else {
+        format!("{} attached", session.attached)
+    };
+    let idle = HumanTime::new(now.saturating_sub(session.activity)).to_human(2);
+    format!(
+        "{:<16} {:<11} {:<12} active {} ago",
+        session.name,
+        plural(session.windows, "window"),
+        attached,
+        idle
+    )
+}
+
+/// Parse answer to the prompt, empty answer selects the first session
+pub fn parse_choice(answer: &str, sessions: &[SessionInfo]) -> Option<Choice> {
+    match answer.trim() {
+        "" => sessions.first().map(|s| Choice::Attach(s.name.clone())),
+        "n" | "new" => Some(Choice::New),
+        "q" | "quit" => Some(Choice::Quit),
+        answer => {
+            // tmux names sessions by number, so name takes precedence over index
+            let session = sessions.iter().find(|s| s.name == answer).or_else(|| {
+                let index = answer.parse::<usize>().ok()?.checked_sub(1)?;
+                sessions.get(index)
+            });
+            session.map(|s| Choice::Attach(s.name.clone()))
+        }
+    }
+}
+
+/// List `sessions` on stderr and ask which one to attach until a valid answer is given
+pub fn choose(socket: &str, sessions: &[SessionInfo]) -> io::Result<Choice> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut stderr = io::stderr();
+    writeln!(stderr, "Sessions on socket {}:", socket)?;
+    for (index, session) in sessions.iter().enumerate() {
+        writeln!(stderr, "{:>3}) {}", index + 1, describe(session, now))?;
+    }
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    loop {
+        write!(
+            stderr,
+            "Attach to [1-{}] or name, n for new session, q to quit (default 1): ",
+            sessions.len()
+        )?;
+        stderr.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            // end of input, e.g. Ctrl-D
+            writeln!(stderr)?;
+            return Ok(Choice::Quit);
+        }
+        match parse_choice(&answer, sessions) {
+            Some(choice) => return Ok(choice),
+            None => writeln!(stderr, "Invalid choice: {}", answer.trim())?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(name: &str, windows: u32, attached: u32, activity: u64) -> SessionInfo {
+        SessionInfo {
+            id: "$0".to_string(),
+            name: name.to_string(),
+            windows,
+            attached,
+            created: 0,
+            activity,
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        let now = 10_000;
+        assert_eq!(
+            describe(&session("0", 1, 0, now - 90), now),
+            "0                1 window    detached     active 1m 30s ago"
+        );
+        assert_eq!(
+            describe(&session("work", 3, 2, now - 7500), now),
+            "work             3 windows   2 attached   active 2h 5m ago"
+        );
+    }
+
+    #[test]
+    fn test_parse_choice() {
+        let sessions = [
+            session("0", 1, 0, 0),
+            session("work", 2, 1, 0),
+            session("2", 1, 0, 0),
+        ];
+        let attach = |name: &str| Some(Choice::Attach(name.to_string()));
+        assert_eq!(parse_choice("\n", &sessions), attach("0"));
+        assert_eq!(parse_choice("1\n", &sessions), attach("0"));
+        assert_eq!(parse_choice("2\n", &sessions), attach("2"));
+        assert_eq!(parse_choice("work", &sessions), attach("work"));
+        assert_eq!(parse_choice("n", &sessions), Some(Choice::New));
+        assert_eq!(parse_choice("q", &sessions), Some(Choice::Quit));
+        assert_eq!(parse_choice("0", &sessions), attach("0"));
+        assert_eq!(parse_choice("3", &sessions), attach("2"));
+        assert_eq!(parse_choice("4", &sessions), None);
+        assert_eq!(parse_choice("other", &sessions), None);
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+use std::{env, fs};
+
+use log::{debug, error, info, warn};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+use nmk::tmux::version::Version;
+
+use crate::tmux::{server_socket_path, Tmux};
+
+/// Server on socket as seen by installed tmux client
+#[derive(Debug, Eq, PartialEq)]
+pub enum ServerState {
+    NotRunning,
+    Running,
+    /// Socket file is left but no server accepts connection, e.g. after crash
+    Dead,
+    /// Server is started by another tmux binary, e.g. before package upgrade
+    Mismatch {
+        pid: Option<i32>,
+        message: String,
+    },
+}
+
+/// Error of tmux client talking to server of another version
+fn is_version_mismatch(message: &str) -> bool {
+    // "protocol version mismatch (client 8, server 7)" or "server version is too old for client"
+    message.contains("version mismatch") || message.contains("version is too old")
+}
+
+/// Inodes of unix sockets bound to `path` in contents of `/proc/net/unix`
+fn socket_inodes(net_unix: &str, path: &Path) -> Vec<u64> {
+    let path = path.to_string_lossy();
+    net_unix
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            // Num RefCount Protocol Flags Type St Inode Path
+            let mut fields = line.split_whitespace().skip(6);
+            let inode = fields.next()?;
+            if fields.next()? == path {
+                inode.parse().ok()
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Pid of process holding socket bound to `path`, that is tmux server
+fn server_pid(path: &Path) -> Option<i32> {
+    let inodes = socket_inodes(&fs::read_to_string("/proc/net/unix").ok()?, path);
+    let targets: Vec<String> = inodes.iter().map(|i| format!("socket:[{}]", i)).collect();
+    for entry in fs::read_dir("/proc").ok()?.flatten() {
+        let pid = match entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let fds = match fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            // process of another user or exited
+            Err(_) => continue,
+        };
+        for fd in fds.flatten() {
+            if let Ok(link) = fs::read_link(fd.path()) {
+                if targets.iter().any(|t| link.as_os_str() == t.as_str()) {
+                    return Some(pid);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Check if both paths are the same file with unchanged content, `/proc/<pid>/exe` of
+/// an upgraded binary refers to the deleted file so it differs from the installed one
+fn is_same_binary(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => {
+            a.dev() == b.dev()
+                && a.ino() == b.ino()
+                && a.mtime() == b.mtime()
+                && a.mtime_nsec() == b.mtime_nsec()
+                && a.len() == b.len()
+        }
+        _ => false,
+    }
+}
+
+/// Pid of server in `TMUX` of the form `<socket path>,<server pid>,<session id>` if it
+/// listens on `path`
+fn tmux_env_pid(value: &str, path: &Path) -> Option<i32> {
+    let mut fields = value.split(',');
+    if Path::new(fields.next()?) != path {
+        return None;
+    }
+    fields.next()?.parse().ok()
+}
+
+/// Check server on `socket` before exec so that tmux doesn't fail after attaching
+///
+/// Inside the server, its pid is known from `TMUX` and server running `bin` is assumed to
+/// work. Otherwise `bin` asks server for its pid, which fails if client can't talk to it,
+/// and only then `/proc` is scanned to find the server process.
+pub fn probe(socket: &str, bin: &Path) -> ServerState {
+    let path = server_socket_path(socket);
+    match UnixStream::connect(&path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => return ServerState::Dead,
+        Err(_) => return ServerState::NotRunning,
+    }
+    let env_pid = env::var("TMUX")
+        .ok()
+        .and_then(|value| tmux_env_pid(&value, &path));
+    if let Some(pid) = env_pid {
+        if is_same_binary(&server_exe(pid), bin) {
+            return ServerState::Running;
+        }
+        debug!("tmux server {} runs another binary, checking it", pid);
+    }
+    let output = Command::new(bin)
+        .args(["-L", socket, "display-message", "-p", "#{pid}"])
+        .stdin(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if !output.status.success() => {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            if is_version_mismatch(&message) {
+                let pid = env_pid.or_else(|| server_pid(&path));
+                ServerState::Mismatch { pid, message }
+            } else {
+                ServerState::Running
+            }
+        }
+        _ => ServerState::Running,
+    }
+}
+
+/// Binary the server process runs, it remains available after the package is upgraded
+fn server_exe(pid: i32) -> PathBuf {
+    PathBuf::from(format!("/proc/{}/exe", pid))
+}
+
+fn ask(prompt: &str) -> io::Result<String> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{}", prompt)?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase())
+}
+
+fn remove_socket(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Stop server with its own binary, then signal it if that fails
+fn kill_server(socket: &str, pid: i32) -> io::Result<()> {
+    let killed = Command::new(server_exe(pid))
+        .args(["-L", socket, "kill-server"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !killed {
+        debug!("Sending SIGTERM to tmux server {}", pid);
+        kill(Pid::from_raw(pid), Signal::SIGTERM).map_err(io::Error::other)?;
+    }
+    let path = server_socket_path(socket);
+    for _ in 0..20 {
+        if UnixStream::connect(&path).is_err() {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    remove_socket(&path)
+}
+
+/// Tmux running binary of server, version falls back to installed one if unknown
+fn old_tmux(pid: i32, installed: Version) -> Tmux {
+    let bin = server_exe(pid);
+    let version = Command::new(&bin)
+        .arg("-V")
+        .output()
+        .ok()
+        .and_then(|output| Version::from_version_output(&output.stdout).ok())
+        .unwrap_or_else(|| {
+            warn!(
+                "Unknown version of {}, assuming {}",
+                bin.display(),
+                installed
+            );
+            installed
+        });
+    Tmux { bin, version }
+}
+
+/// Probe server on `socket` and ask how to recover if installed tmux can't use it
+///
+/// Without a terminal to ask, a dead socket is removed and a mismatched server is an error.
+pub fn recover(socket: &str, tmux: Tmux) -> io::Result<Tmux> {
+    let interactive = io::stdin().is_terminal();
+    match probe(socket, &tmux.bin) {
+        ServerState::NotRunning | ServerState::Running => Ok(tmux),
+        ServerState::Dead => {
+            let path = server_socket_path(socket);
+            if interactive {
+                let prompt = format!(
+                    "Socket {} is left by a tmux server that is no longer running, remove it? [Y/n] ",
+                    path.display()
+                );
+                if !matches!(ask(&prompt)?.as_str(), "" | "y" | "yes") {
+                    exit(1);
+                }
+            }
+            info!("Removing dead socket {}", path.display());
+            remove_socket(&path)?;
+            Ok(tmux)
+        }
+        ServerState::Mismatch { pid, message } => {
+            error!("Cannot use tmux server on socket {}: {}", socket, message);
+            let pid = match pid {
+                Some(pid) if interactive => pid,
+                Some(pid) => {
+                    error!(
+                        "Attach with {} -L {} or stop it to start a new server",
+                        server_exe(pid).display(),
+                        socket
+                    );
+                    exit(1);
+                }
+                None => {
+                    error!(
+                        "Cannot find pid of tmux server, stop it with `tmux -L {} kill-server` \
+                        of the tmux version that started it",
+                        socket
+                    );
+                    exit(1);
+                }
+            };
+            eprintln!(
+                "  o) attach with binary of the server {}",
+                server_exe(pid).display()
+            );
+            eprintln!("  r) restart the server, its sessions are lost");
+            eprintln!("  q) quit");
+            loop {
+                match ask("Choice [o/r/q] (default o): ")?.as_str() {
+                    "" | "o" => return Ok(old_tmux(pid, tmux.version)),
+                    "r" => {
+                        kill_server(socket, pid)?;
+                        return Ok(tmux);
+                    }
+                    "q" => exit(0),
+                    answer => eprintln!("Invalid choice: {}", answer),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_version_mismatch() {
+        assert!(is_version_mismatch(
+            "protocol version mismatch (client 8, server 7)"
+        ));
+        assert!(is_version_mismatch("server version is too old for client"));
+        assert!(!is_version_mismatch("no server running on /tmp/tmux-0/nmk"));
+    }
+
+    #[test]
+    fn test_socket_inodes() {
+        let net_unix = "Num       RefCount Protocol Flags    Type St Inode Path\n\
+            000000000db231be: 00000002 00000000 00010000 0001 01 59769 /tmp/tmux-0/nmk\n\
+            000000000e2b6c21: 00000003 00000000 00000000 0001 03   924\n\
+            000000001da8da61: 00000003 00000000 00000000 0001 03 61234 /tmp/tmux-0/nmk\n\
+            000000003ff3db34: 00000002 00000000 00010000 0001 01 68717 /tmp/tmux-0/other\n";
+        assert_eq!(
+            socket_inodes(net_unix, Path::new("/tmp/tmux-0/nmk")),
+            vec![59769, 61234]
+        );
+    }
+
+    #[test]
+    fn test_tmux_env_pid() {
+        let path = Path::new("/tmp/tmux-0/nmk");
+        assert_eq!(tmux_env_pid("/tmp/tmux-0/nmk,1234,0", path), Some(1234));
+        assert_eq!(tmux_env_pid("/tmp/tmux-0/other,1234,0", path), None);
+        assert_eq!(tmux_env_pid("/tmp/tmux-0/nmk", path), None);
+    }
+
+    #[test]
+    fn test_is_same_binary() {
+        let exe = std::env::current_exe().unwrap();
+        assert!(is_same_binary(&exe, &exe));
+        assert!(is_same_binary(Path::new("/proc/self/exe"), &exe));
+        assert!(!is_same_binary(&exe, Path::new("/nonexistent")));
+    }
+}
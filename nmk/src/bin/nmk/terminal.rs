@@ -1,6 +1,10 @@
 use std::env;
 
+use log::debug;
+
 use nmk::container;
+use nmk::terminfo::Terminfo;
+use nmk::tmux::theme::ColorDepth;
 
 fn is_term_256_color(term: impl AsRef<str>) -> bool {
     let terms = [
@@ -19,13 +23,76 @@ fn is_colorterm_256_color(term: impl AsRef<str>) -> bool {
 
 pub fn support_256_color() -> bool {
     let arr = [
-        || env::var("TERM").map_or(false, is_term_256_color),
-        || env::var("COLORTERM").map_or(false, is_colorterm_256_color),
+        || env::var("TERM").is_ok_and(is_term_256_color),
+        || env::var("COLORTERM").is_ok_and(is_colorterm_256_color),
         || container::is_containerized(),
     ];
     arr.iter().any(|f| f())
 }
 
+fn is_colorterm_true_color(term: impl AsRef<str>) -> bool {
+    ["truecolor", "24bit"].contains(&term.as_ref())
+}
+
+/// Terminals known to support 24-bit colour even if their terminfo doesn't say so
+fn is_term_true_color(term: impl AsRef<str>) -> bool {
+    let term = term.as_ref();
+    let terms = ["alacritty", "foot", "foot-extra", "wezterm", "xterm-kitty"];
+    terms.contains(&term) || term.ends_with("-direct")
+}
+
+fn is_term_program_true_color(program: impl AsRef<str>) -> bool {
+    ["WezTerm", "iTerm.app"].contains(&program.as_ref())
+}
+
+#[derive(Debug)]
+pub struct Capabilities {
+    pub color_depth: ColorDepth,
+    /// `TERM` of the outer terminal
+    pub term: Option<String>,
+    /// Outer terminal is another tmux, its prefix has to be avoided
+    pub nested: bool,
+}
+
+impl Capabilities {
+    pub fn support_256_color(&self) -> bool {
+        self.color_depth >= ColorDepth::Indexed256
+    }
+}
+
+/// Detect colour support of terminal from environment and terminfo database
+pub fn probe(force_256_color: bool) -> Capabilities {
+    let term = env::var("TERM").ok().filter(|t| !t.is_empty());
+    let terminfo = term.as_deref().and_then(Terminfo::load);
+    let is_true_color = [
+        env::var("COLORTERM").is_ok_and(is_colorterm_true_color),
+        env::var("TERM_PROGRAM").is_ok_and(is_term_program_true_color),
+        term.as_deref().is_some_and(is_term_true_color),
+        terminfo.as_ref().is_some_and(Terminfo::has_true_color),
+    ];
+    let color_depth = if is_true_color.iter().any(|&b| b) {
+        ColorDepth::TrueColor
+    } else if force_256_color
+        || term.as_deref().is_some_and(|t| t.ends_with("-256color"))
+        || terminfo
+            .as_ref()
+            .and_then(|t| t.colors)
+            .is_some_and(|n| n >= 256)
+        || support_256_color()
+    {
+        ColorDepth::Indexed256
+    } else {
+        ColorDepth::Basic
+    };
+    let capabilities = Capabilities {
+        color_depth,
+        term,
+        nested: crate::tmux::is_nested(),
+    };
+    debug!("Terminal capabilities: {:?}", capabilities);
+    capabilities
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,4 +116,16 @@ mod tests {
         assert!(!is_colorterm_256_color("unknown"));
         assert!(!is_colorterm_256_color(""));
     }
+
+    #[test]
+    fn test_is_true_colorterm() {
+        assert!(is_colorterm_true_color("truecolor"));
+        assert!(is_colorterm_true_color("24bit"));
+        assert!(!is_colorterm_true_color("gnome-terminal"));
+        assert!(is_term_true_color("alacritty"));
+        assert!(is_term_true_color("xterm-kitty"));
+        assert!(is_term_true_color("xterm-direct"));
+        assert!(!is_term_true_color("xterm-256color"));
+        assert!(is_term_program_true_color("WezTerm"));
+    }
 }
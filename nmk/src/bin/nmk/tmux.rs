@@ -1,59 +1,207 @@
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
-use std::{env, fs, io};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+use std::{env, io};
 
-use log::debug;
+use log::{debug, warn};
 use nix::unistd::Uid;
 
+use nmk::config::Config;
 use nmk::consts::bin::{TMUX, ZSH};
 use nmk::consts::env::NMK_TMUX_VERSION;
-use nmk::tmux::config::Context;
+use nmk::home::NmkHome;
+use nmk::probe_cache;
+use nmk::setup::write_atomic;
+use nmk::terminfo;
+use nmk::tmp_dir;
+use nmk::tmux::config::{default_terminal, Context};
+use nmk::tmux::layout;
+use nmk::tmux::layout::Layout;
+use nmk::tmux::nested;
+use nmk::tmux::status::Segment;
+use nmk::tmux::theme::{self, ColorDepth};
 use nmk::tmux::version::{TmuxVersionError, Version};
 
 use crate::cmdline::CmdOpt;
+use crate::terminal::Capabilities;
 use crate::utils::print_usage_time;
 
+const CONFIG_SUFFIX: &str = ".tmux.conf";
+const PROBE_CACHE_FILE: &str = "probe-cache.json";
+const TMUX_VERSION_PROBE: &str = "tmux version";
+/// Rendered config unused for this long is removed, tmux reads it only on start
+const STALE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
 pub struct Tmux {
     pub bin: PathBuf,
     pub version: Version,
 }
 
-fn find_version() -> Result<Version, TmuxVersionError> {
+fn version_output(bin: &Path) -> Vec<u8> {
+    let Output {
+        status,
+        stderr,
+        stdout,
+    } = Command::new(bin)
+        .arg("-V")
+        .output()
+        .expect("failed to get tmux version output");
+    if !status.success() {
+        panic!(
+            "tmux exit with status code: {:?}, error: {}",
+            status.code(),
+            String::from_utf8_lossy(&stderr)
+        );
+    }
+    stdout
+}
+
+fn find_version(bin: &Path) -> Result<Version, TmuxVersionError> {
     if let Ok(s) = std::env::var(NMK_TMUX_VERSION) {
         debug!("Using tmux version from environment variable");
-        Version::from_version(&s)
-    } else {
-        let Output {
-            status,
-            stderr,
-            stdout,
-        } = Command::new(TMUX)
-            .arg("-V")
-            .output()
-            .expect("failed to get tmux version output");
-        if !status.success() {
-            panic!(
-                "tmux exit with status code: {:?}, error: {}",
-                status.code(),
-                String::from_utf8_lossy(&stderr)
-            );
-        }
-        Version::from_version_output(&stdout)
+        return Version::from_version(&s);
+    }
+    // keyed by the binary so upgrading tmux misses the cache
+    let cached = probe_cache::file_key(bin).and_then(|key| {
+        probe_cache::with_shared(|cache| {
+            cache.get_or_probe(TMUX_VERSION_PROBE, key, || {
+                let version = Version::from_version_output(&version_output(bin)).ok()?;
+                Some(version.as_str().to_string())
+            })
+        })
+    });
+    match cached {
+        Some(version) => Version::from_version(&version),
+        None => Version::from_version_output(&version_output(bin)),
     }
 }
 
 impl Tmux {
     pub fn new() -> Tmux {
-        let bin = which::which(TMUX).expect("cannot find tmux binary");
-        let version = find_version().expect("find tmux version error");
+        let bin = probe_cache::which(TMUX).expect("cannot find tmux binary");
+        let version = find_version(&bin).expect("find tmux version error");
         Tmux { bin, version }
     }
 
-    pub fn exec(&self, cmd_opt: &CmdOpt, config: &Path, is_color_term: bool) -> ! {
+    /// Arguments to attach to default session or create it
+    pub fn default_session_args(&self) -> Vec<String> {
+        let mut args = vec!["new-session".to_string(), "-A".to_string()];
+        if self.version < Version::V31 {
+            args.extend(["-s".to_string(), "0".to_string()]);
+        }
+        args
+    }
+
+    /// Arguments to attach to named session or create it
+    pub fn attach_session_args(&self, session: &str) -> Vec<String> {
+        vec![
+            "new-session".to_string(),
+            "-A".to_string(),
+            "-s".to_string(),
+            session.to_string(),
+        ]
+    }
+
+    /// Arguments to create a new session named by tmux
+    pub fn new_session_args(&self) -> Vec<String> {
+        vec!["new-session".to_string()]
+    }
+
+    /// Arguments to attach to session or create it with windows of layout
+    pub fn layout_session_args(&self, socket: &str, session: &str, layout: &Layout) -> Vec<String> {
+        if self.has_session(socket, session) {
+            debug!("Attaching to existing session {}", session);
+            return vec![
+                "attach-session".to_string(),
+                "-t".to_string(),
+                format!("={}", session),
+            ];
+        }
+        let first = vec![
+            "new-session".to_string(),
+            "-s".to_string(),
+            session.to_string(),
+        ];
+        layout::chain(layout.commands(first, self.version))
+    }
+
+    /// Arguments to start new server with saved sessions then attach to the first one
+    pub fn restore_session_args(&self, layouts: &[Layout]) -> Vec<String> {
+        let mut commands = Vec::new();
+        for layout in layouts {
+            let session = layout.session_name("0");
+            let first = vec![
+                "new-session".to_string(),
+                "-d".to_string(),
+                "-s".to_string(),
+                session.to_string(),
+            ];
+            commands.extend(layout.commands(first, self.version));
+        }
+        if let Some(first) = layouts.first() {
+            commands.push(vec![
+                "attach-session".to_string(),
+                "-t".to_string(),
+                format!("={}", first.session_name("0")),
+            ]);
+        }
+        layout::chain(commands)
+    }
+
+    /// Create session in background on running server
+    pub fn create_detached_session(
+        &self,
+        socket: &str,
+        session: &str,
+        layout: &Layout,
+    ) -> io::Result<()> {
+        let first = vec![
+            "new-session".to_string(),
+            "-d".to_string(),
+            "-s".to_string(),
+            session.to_string(),
+        ];
+        let args = layout::chain(layout.commands(first, self.version));
         let mut cmd = Command::new(TMUX);
-        cmd.args(&["-L", &cmd_opt.socket]);
+        cmd.args(["-L", socket]).args(args);
+        debug!("run command: {:?}", cmd);
+        let status = cmd.status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "tmux exit with status code: {:?}",
+                status.code()
+            )))
+        }
+    }
+
+    /// Same as [`output`] with this binary, which may be the one of running server
+    pub fn output(&self, socket: &str, args: &[&str]) -> io::Result<String> {
+        output_of(&self.bin, socket, args)
+    }
+
+    pub fn has_session(&self, socket: &str, session: &str) -> bool {
+        Command::new(&self.bin)
+            .args(["-L", socket, "has-session", "-t"])
+            .arg(format!("={}", session))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    pub fn exec(
+        &self,
+        cmd_opt: &CmdOpt,
+        config: &Path,
+        is_color_term: bool,
+        session_args: &[String],
+    ) -> ! {
+        let mut cmd = Command::new(&self.bin);
+        cmd.args(["-L", &cmd_opt.socket]);
         if is_color_term {
             cmd.arg("-2");
         }
@@ -62,13 +210,9 @@ impl Tmux {
         }
         cmd.arg("-f");
         cmd.arg(config);
-        // Attach to tmux or create new session
-        cmd.args(&["new-session", "-A"]);
-        if self.version < Version::V31 {
-            cmd.args(&["-s", "0"]);
-        }
+        cmd.args(session_args);
         debug!("exec command: {:?}", cmd);
-        print_usage_time(&cmd_opt);
+        print_usage_time(cmd_opt);
         let err = cmd.exec();
         panic!("exec {:?} fail with {:?}", cmd, err);
     }
@@ -79,34 +223,193 @@ impl Tmux {
         contents: &[u8],
     ) -> io::Result<PathBuf> {
         let nmk_tmp_dir = create_nmk_tmp_dir()?;
-        let config = nmk_tmp_dir.join(format!("{}.tmux.conf", cmd_opt.socket));
-        fs::write(&config, contents)?;
+        // named by content, so racing nmk either reuse the file or write same bytes
+        let config = nmk_tmp_dir.join(format!(
+            "{}-{:016x}{}",
+            cmd_opt.socket,
+            tmp_dir::content_hash(contents),
+            CONFIG_SUFFIX
+        ));
+        if !tmp_dir::touch(&config)? {
+            write_atomic(&config, contents, 0o600)?;
+        }
+        match tmp_dir::remove_stale(&nmk_tmp_dir, &[CONFIG_SUFFIX, ".tmp"], STALE_AGE) {
+            Ok(0) => {}
+            Ok(n) => debug!("Removed {} stale files in {}", n, nmk_tmp_dir.display()),
+            Err(e) => warn!("Failed to clean {}: {}", nmk_tmp_dir.display(), e),
+        }
         Ok(config)
     }
 }
 
-fn create_nmk_tmp_dir() -> io::Result<PathBuf> {
+/// Check if we are running inside a client of tmux server on `socket`
+pub fn is_inside_server(socket: &str) -> bool {
+    current_socket().is_some_and(|name| name == socket)
+}
+
+/// Name of server socket this process runs inside
+pub fn current_socket() -> Option<String> {
+    // TMUX is `<socket path>,<server pid>,<session id>`
+    let value = env::var("TMUX").ok()?;
+    let path = value.split(',').next()?;
+    Some(Path::new(path).file_name()?.to_string_lossy().into_owned())
+}
+
+/// Run tmux command on server and return its stdout
+pub fn output(socket: &str, args: &[&str]) -> io::Result<String> {
+    output_of(Path::new(TMUX), socket, args)
+}
+
+fn output_of(bin: &Path, socket: &str, args: &[&str]) -> io::Result<String> {
+    // without -u, tmux replaces tab separating query fields with `_` unless the locale is UTF-8
+    let output = Command::new(bin)
+        .args(["-u", "-L", socket])
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(io::Error::other(format!(
+            "tmux {:?} exit with status code: {:?}, error: {}",
+            args,
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// Path of tmux server socket, see `-L` in tmux manual
+pub fn server_socket_path(socket: &str) -> PathBuf {
+    let tmux_tmpdir = env::var_os("TMUX_TMPDIR")
+        .filter(|v| !v.is_empty())
+        .map_or_else(|| PathBuf::from("/tmp"), PathBuf::from);
+    tmux_tmpdir
+        .join(format!("tmux-{}", Uid::current()))
+        .join(socket)
+}
+
+/// Check if tmux server is accepting connection, this doesn't spawn any process
+pub fn is_server_running(socket: &str) -> bool {
+    UnixStream::connect(server_socket_path(socket)).is_ok()
+}
+
+/// Private directory of current user for rendered config and caches
+pub fn create_nmk_tmp_dir() -> io::Result<PathBuf> {
     let tmp_dir = env::temp_dir();
     let nmk_tmp_dir = tmp_dir.join(format!("nmk-{}", Uid::current()));
-    if !nmk_tmp_dir.exists() {
-        fs::create_dir(&nmk_tmp_dir)?;
-        let mut permissions = nmk_tmp_dir.metadata()?.permissions();
-        permissions.set_mode(0o700);
-        fs::set_permissions(&nmk_tmp_dir, permissions)?;
-    }
+    tmp_dir::create_private_dir(&nmk_tmp_dir)?;
     Ok(nmk_tmp_dir)
 }
 
-pub fn make_config_context(cmd_opt: &CmdOpt, support_256_color: bool) -> Context {
-    let default_term = if support_256_color {
-        "screen-256color"
+/// Keep probe cache in nmk temp dir, probes still run if it's unusable
+pub fn init_probe_cache() {
+    match create_nmk_tmp_dir() {
+        Ok(dir) => probe_cache::set_shared_path(dir.join(PROBE_CACHE_FILE)),
+        Err(e) => debug!("Probe cache disabled: {}", e),
+    }
+}
+
+/// Path of running nmk binary, quoted for use in shell commands run by tmux
+fn quoted_exe() -> Option<String> {
+    let exe = env::current_exe().ok()?;
+    Some(shell_words::quote(&exe.to_string_lossy()).into_owned())
+}
+
+fn autosave_command(socket: &str) -> Option<String> {
+    Some(format!(
+        "{} -L {} session save --auto",
+        quoted_exe()?,
+        shell_words::quote(socket)
+    ))
+}
+
+/// tmux formats that run `nmk status-segment` for each segment
+///
+/// Pane path is shell quoted by `q:`, tmux before 2.9 lacks it so segment asks server for the path.
+fn status_segment_formats(segments: &[Segment], v: Version) -> Vec<String> {
+    let exe = match quoted_exe() {
+        Some(exe) => exe,
+        None => return Vec::new(),
+    };
+    segments
+        .iter()
+        .map(|s| {
+            let path = if s.needs_path() && v >= Version::V29 {
+                " #{q:pane_current_path}"
+            } else {
+                ""
+            };
+            format!("#({} status-segment {}{})", exe, s.as_str(), path)
+        })
+        .collect()
+}
+
+/// Check if nmk is started inside another tmux, directly or through SSH
+pub fn is_nested() -> bool {
+    let var = |key: &str| env::var(key).ok();
+    nested::is_nested(
+        var("TMUX").as_deref(),
+        var("SSH_CONNECTION").as_deref(),
+        var("TERM").as_deref(),
+    )
+}
+
+pub fn make_config_context(
+    cmd_opt: &CmdOpt,
+    nmk_home: &NmkHome,
+    capabilities: &Capabilities,
+    v: Version,
+    config: &Config,
+) -> Context {
+    let color_depth = capabilities.color_depth;
+    let default_term = default_terminal(v, color_depth, |name| terminfo::find(name).is_some());
+    let true_color_term = if color_depth == ColorDepth::TrueColor {
+        Some(capabilities.term.clone().unwrap_or_else(|| "*".to_string()))
     } else {
-        "screen"
+        None
     };
-    Context {
-        support_256_color,
+    let mut context = Context {
+        color_depth,
+        true_color_term,
+        theme: theme::resolve(nmk_home.path(), config.theme.as_deref(), color_depth),
         detach_on_destroy: cmd_opt.detach_on_destroy,
         default_term: default_term.to_owned(),
-        default_shell: which::which(ZSH).expect("zsh not found"),
-    }
+        default_shell: probe_cache::which(ZSH).expect("zsh not found"),
+        reload_command: quoted_exe()
+            .map(|exe| format!("{} -L {} reload", exe, shell_words::quote(&cmd_opt.socket))),
+        clipboard_command: quoted_exe(),
+        set_clipboard: config.clipboard.uses_osc52(),
+        autosave_command: if config.session.autosave {
+            autosave_command(&cmd_opt.socket)
+        } else {
+            None
+        },
+        status_left: status_segment_formats(&config.status.left, v),
+        status_right: status_segment_formats(&config.status.right, v),
+        ..Context::default()
+    };
+    apply_keys(&mut context, config, capabilities.nested);
+    context
+}
+
+/// Set prefix and key bindings of `context` from `config`
+fn apply_keys(context: &mut Context, config: &Config, nested: bool) {
+    let outer = config.keys.prefix();
+    context.prefix = if config.nested.detect && nested {
+        let prefix = config.nested.prefix(outer);
+        if prefix == outer {
+            warn!(
+                "Nested prefix {} is the same as outer prefix, it only reaches outer tmux",
+                prefix
+            );
+        }
+        debug!("Nested tmux detected, using prefix {}", prefix);
+        prefix.to_string()
+    } else {
+        outer.to_string()
+    };
+    context.off_toggle_key = config.nested.toggle_key.clone();
+    context.schemes = config.keys.schemes.clone();
+    context.key_overrides = config.keys.bind.clone();
 }
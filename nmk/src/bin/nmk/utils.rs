@@ -1,12 +1,28 @@
-use log::debug;
+use std::io;
+
+use log::{debug, warn};
+
+use nmk::timing::{self, Format};
 
 use crate::cmdline::CmdOpt;
 
 pub fn print_usage_time(cmd_opt: &CmdOpt) {
-    let elapsed = cmd_opt.start_time.elapsed().as_millis();
+    let elapsed = cmd_opt.start_time.elapsed();
     if cmd_opt.usage {
-        println!("{} ms.", elapsed);
+        // stderr so that it doesn't mix with config printed by --render
+        eprintln!("{} ms.", elapsed.as_millis());
     } else {
-        debug!("Usage time: {} ms.", elapsed);
+        debug!("Usage time: {} ms.", elapsed.as_millis());
+    }
+    if let Some(format) = cmd_opt.profile_startup {
+        let phases = timing::phases();
+        let mut stderr = io::stderr();
+        let result = match format.unwrap_or(Format::Text) {
+            Format::Text => timing::write_text(&mut stderr, &phases, elapsed),
+            Format::Json => timing::write_json(&mut stderr, &phases, elapsed),
+        };
+        if let Err(e) = result {
+            warn!("Failed to print startup profile: {}", e);
+        }
     }
 }
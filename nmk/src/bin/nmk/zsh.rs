@@ -7,6 +7,7 @@ use nmk::config::one_hot;
 use nmk::consts::bin::ZSH;
 use nmk::consts::env::NMK_ZSH_GLOBAL_RCS;
 use nmk::home::NmkHome;
+use nmk::probe_cache;
 
 use crate::cmdline::CmdOpt;
 use crate::entrypoint::set_env;
@@ -39,12 +40,12 @@ pub fn init(nmk_home: &NmkHome) {
 }
 
 pub fn exec_login_shell(cmd_opt: &CmdOpt) -> ! {
-    let zsh = which::which(ZSH).expect("failed to locate zsh");
+    let zsh = probe_cache::which(ZSH).expect("failed to locate zsh");
     let mut cmd = Command::new(&zsh);
     cmd.env("SHELL", zsh);
     // Signal zsh that it is a login shell by prepend - to arg0
     cmd.arg0("-zsh");
-    print_usage_time(&cmd_opt);
+    print_usage_time(cmd_opt);
     let err = cmd.exec();
     panic!("exec {:?} fail with {:?}", cmd, err);
 }
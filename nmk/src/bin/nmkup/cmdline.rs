@@ -11,6 +11,8 @@ pub struct CmdOpt {
     pub force: bool,
     #[structopt(short, long, help = "Backup important files before update")]
     pub backup: bool,
+    #[structopt(long, help = "Do not snapshot modified files before updating dotfiles")]
+    pub no_snapshot: bool,
     #[structopt(
         long,
         value_name = "file",
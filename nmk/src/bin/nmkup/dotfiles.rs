@@ -4,6 +4,8 @@ use std::path::Path;
 
 use log::{debug, info};
 
+use nmk::backup::snapshot_before_update;
+use nmk::config::Config;
 use nmk::dotfiles::{extract_dotfiles, uninstall};
 use nmk::gcs::{download_file, get_object_meta, get_object_meta_url, ObjectMeta};
 use nmk::home::NmkHome;
@@ -23,6 +25,19 @@ fn is_dotfiles_up2date(meta_path: &Path, gcs_meta: &ObjectMeta) -> bool {
     cached_meta.generation == gcs_meta.generation
 }
 
+fn snapshot(nmk_home: &NmkHome) -> nmk::Result<()> {
+    let config = Config::load(nmk_home.path())?.backup;
+    match snapshot_before_update(nmk_home, &config)? {
+        Some(path) => info!(
+            "{}: Snapshot of local files written to {}",
+            TAG,
+            path.display()
+        ),
+        None => debug!("{}: Nothing to snapshot.", TAG),
+    }
+    Ok(())
+}
+
 pub fn install_or_update(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Result<()> {
     let nmk_home_path = nmk_home.path().as_path();
     if !nmk_home_path.exists() {
@@ -51,6 +66,9 @@ pub fn install_or_update(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Result<()
         info!("{}: Already up to date.", TAG);
     } else {
         if meta_do_exist {
+            if !cmd_opt.no_snapshot {
+                snapshot(nmk_home)?;
+            }
             // uninstall old version, we don't care if it success or not
             uninstall(nmk_home.path())?;
         }
@@ -64,17 +82,3 @@ pub fn install_or_update(cmd_opt: &CmdOpt, nmk_home: &NmkHome) -> nmk::Result<()
     }
     Ok(())
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_strip_first_component() {
-        let p = Path::new(".nmk/bin/nmk");
-        let actual = strip_components(&p, 1);
-        assert_eq!(actual, Path::new("bin/nmk"));
-        let actual = strip_components(&p, 2);
-        assert_eq!(actual, Path::new("nmk"));
-    }
-}
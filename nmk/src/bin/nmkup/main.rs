@@ -1,10 +1,10 @@
 use std::path::Path;
 use std::process::exit;
 
-use dirs::home_dir;
 use log::error;
 
 use nmk::backup::backup_files;
+use nmk::config::Config;
 use nmk::home::NmkHome;
 use nmk::platform;
 
@@ -35,9 +35,8 @@ fn main() -> nmk::Result<()> {
         exit(1);
     }
     if cmd_opt.backup {
-        let home = home_dir().expect("failed to find home directory");
-        let output_tar = home.join("nmk-backup.tar");
-        backup_files(&nmk_home, &output_tar)?;
+        let config = Config::load(nmk_home.path())?.backup;
+        backup_files(&nmk_home, &config, &config.output_dir())?;
     }
     dotfiles::install_or_update(&cmd_opt, &nmk_home)?;
     let entrypoint_installation = entrypoint::install_or_update(&cmd_opt, &nmk_home)?;
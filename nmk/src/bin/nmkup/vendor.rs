@@ -46,7 +46,7 @@ fn filter_by_os_release() -> impl FnMut(&ObjectMeta) -> bool {
     });
     move |item: &ObjectMeta| {
         // Try to filter by os-release data, if we can't determine os-release, don't filter at all.
-        pattern.map_or(true, |pat| item.name.contains(pat))
+        pattern.is_none_or(|pat| item.name.contains(pat))
     }
 }
 
@@ -66,7 +66,7 @@ fn filter_by_arch() -> impl FnMut(&ObjectMeta) -> bool {
 fn get_display_name(objects: &[ObjectMeta]) -> Vec<&str> {
     objects
         .iter()
-        .flat_map(|obj| obj.name.split('/').last())
+        .flat_map(|obj| obj.name.split('/').next_back())
         .collect()
 }
 
@@ -1,14 +1,24 @@
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
 use std::{fs, io};
 
-use log::{debug, info};
+use glob::{MatchOptions, Pattern};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use xz2::write::XzEncoder;
 
-use crate::home::NmkHome;
+use crate::datetime::DateTime;
+use crate::dotfiles;
+use crate::home::{expand_tilde, NmkHome};
+use crate::platform;
 
-const BACKUP_PATHS: &[&str] = &[
+/// Default files to backup, relative to NMK_HOME
+pub const BACKUP_PATHS: &[&str] = &[
     ".tmux_history",
+    "nmk.toml",
     "zsh/.zsh_history",
     "zsh/completion/",
     "zsh/zprofile",
@@ -17,40 +27,413 @@ const BACKUP_PATHS: &[&str] = &[
     "zsh/zshrc.pre.d/",
 ];
 
-fn should_backup_dir(dir: &Path) -> bool {
-    fs::read_dir(dir)
-        .unwrap_or_else(|e| panic!("Failed to read dir {}. Error\n{:?}", dir.display(), e))
-        .flatten() // We ignore any IO error
-        .any(|p| p.file_name() != ".empty")
+const DEFAULT_EXCLUDE: &[&str] = &["**/.empty"];
+pub const ARCHIVE_PREFIX: &str = "nmk-backup-";
+pub const SNAPSHOT_PREFIX: &str = "nmk-pre-update-";
+const ARCHIVE_SUFFIX: &str = ".tar.xz";
+const MANIFEST: &str = "MANIFEST.toml";
+const XZ_PRESET: u32 = 6;
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Glob patterns relative to NMK_HOME, a trailing `/` matches everything inside directory
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Where archives are written, leading `~` is expanded, default to `$HOME/nmk-backup`
+    pub directory: Option<PathBuf>,
+    /// Number of archives to keep, 0 means never delete old archives
+    pub keep: usize,
+    /// Number of automatic pre-update snapshots to keep, 0 means never delete old snapshots
+    pub snapshot_keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        let to_vec = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        BackupConfig {
+            include: to_vec(BACKUP_PATHS),
+            exclude: to_vec(DEFAULT_EXCLUDE),
+            directory: None,
+            keep: 10,
+            snapshot_keep: 5,
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn output_dir(&self) -> PathBuf {
+        self.directory
+            .as_deref()
+            .map(expand_tilde)
+            .unwrap_or_else(|| {
+                dirs::home_dir()
+                    .expect("failed to find home directory")
+                    .join("nmk-backup")
+            })
+    }
+}
+
+/// Metadata stored as the first entry of every archive
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    pub host: String,
+    pub nmk_version: String,
+    pub created: String,
+    pub files: Vec<String>,
+}
+
+impl Manifest {
+    fn new(files: &[PathBuf]) -> Self {
+        let nmk_version = match option_env!("GIT_SHORT_SHA") {
+            Some(sha) => format!("{} #{}", env!("CARGO_PKG_VERSION"), sha),
+            None => env!("CARGO_PKG_VERSION").to_owned(),
+        };
+        Manifest {
+            host: platform::hostname(),
+            nmk_version,
+            created: DateTime::now().to_string(),
+            files: files
+                .iter()
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect(),
+        }
+    }
+}
+
+fn compile_pattern(s: &str) -> crate::Result<Pattern> {
+    let s = s.trim_start_matches("./");
+    if s.ends_with('/') {
+        Ok(Pattern::new(&format!("{}**", s))?)
+    } else {
+        Ok(Pattern::new(s)?)
+    }
+}
+
+/// Leading components of pattern that contain no wildcard
+fn literal_prefix(pattern: &str) -> PathBuf {
+    Path::new(pattern.trim_start_matches("./"))
+        .components()
+        .take_while(|c| match c {
+            Component::Normal(s) => !s.to_string_lossy().contains(&['*', '?', '['][..]),
+            _ => false,
+        })
+        .collect()
 }
 
-pub fn backup_files(nmk_home: &NmkHome, ar_path: &Path) -> io::Result<()> {
-    let mut ar = tar::Builder::new(BufWriter::new(File::create(&ar_path)?));
+fn walk(base: &Path, relative: PathBuf, output: &mut Vec<PathBuf>) -> io::Result<()> {
+    let path = base.join(&relative);
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if metadata.is_dir() {
+        for entry in fs::read_dir(&path)? {
+            walk(base, relative.join(entry?.file_name()), output)?;
+        }
+    } else {
+        output.push(relative);
+    }
+    Ok(())
+}
+
+/// Find files under `nmk_path` matching any of `include` and none of `exclude`
+///
+/// Returned paths are relative to `nmk_path` and sorted.
+pub fn collect_files(
+    nmk_path: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> crate::Result<Vec<PathBuf>> {
+    let exclude = exclude
+        .iter()
+        .map(|s| compile_pattern(s))
+        .collect::<crate::Result<Vec<_>>>()?;
+    let mut files = BTreeSet::new();
+    for pattern in include {
+        let compiled = compile_pattern(pattern)?;
+        let mut candidates = Vec::new();
+        walk(nmk_path, literal_prefix(pattern), &mut candidates)?;
+        files.extend(candidates.into_iter().filter(|p| {
+            compiled.matches_path_with(p, MATCH_OPTIONS)
+                && !exclude
+                    .iter()
+                    .any(|e| e.matches_path_with(p, MATCH_OPTIONS))
+        }));
+    }
+    Ok(files.into_iter().collect())
+}
+
+/// Archives created in the same second get a counter after `_`
+fn archive_name(prefix: &str, time: DateTime, counter: usize) -> String {
+    if counter == 0 {
+        format!("{}{}{}", prefix, time.to_compact(), ARCHIVE_SUFFIX)
+    } else {
+        format!(
+            "{}{}_{:02}{}",
+            prefix,
+            time.to_compact(),
+            counter,
+            ARCHIVE_SUFFIX
+        )
+    }
+}
+
+/// Chronological order of archive name, counter is compared as a number
+fn archive_sort_key<'a>(name: &'a str, prefix: &str) -> (&'a str, usize) {
+    let stem = name.strip_prefix(prefix).unwrap_or(name);
+    let stem = stem.strip_suffix(ARCHIVE_SUFFIX).unwrap_or(stem);
+    match stem.split_once('_') {
+        Some((time, counter)) => (time, counter.parse().unwrap_or(0)),
+        None => (stem, 0),
+    }
+}
+
+fn partial_path(ar_path: &Path) -> PathBuf {
+    let mut name = OsString::from(".partial-");
+    name.push(ar_path.file_name().expect("archive must have file name"));
+    ar_path.with_file_name(name)
+}
+
+/// Pick a name that is not taken and create its partial file
+///
+/// Write to hidden file first so pruning never see incomplete archive.
+fn create_partial(output_dir: &Path, prefix: &str) -> io::Result<(PathBuf, PathBuf, File)> {
+    let time = DateTime::now();
+    let mut counter = 0;
+    loop {
+        let ar_path = output_dir.join(archive_name(prefix, time, counter));
+        let partial_path = partial_path(&ar_path);
+        counter += 1;
+        if ar_path.exists() {
+            continue;
+        }
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&partial_path)
+        {
+            Ok(file) => return Ok((ar_path, partial_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn is_archive_name(name: &str, prefix: &str) -> bool {
+    name.starts_with(prefix) && name.ends_with(ARCHIVE_SUFFIX)
+}
+
+/// Write `files` (relative to `nmk_path`) with a manifest into a new timestamped archive
+pub fn create_archive(
+    nmk_path: &Path,
+    files: &[PathBuf],
+    output_dir: &Path,
+    prefix: &str,
+) -> crate::Result<PathBuf> {
+    fs::create_dir_all(output_dir)?;
+    let (ar_path, partial_path, file) = create_partial(output_dir, prefix)?;
+    if let Err(e) = write_archive(file, nmk_path, files) {
+        if let Err(remove_err) = fs::remove_file(&partial_path) {
+            warn!(
+                "Failed to remove {}: {}",
+                partial_path.display(),
+                remove_err
+            );
+        }
+        return Err(e);
+    }
+    fs::rename(&partial_path, &ar_path)?;
+    Ok(ar_path)
+}
+
+fn write_archive(file: File, nmk_path: &Path, files: &[PathBuf]) -> crate::Result<()> {
+    let encoder = XzEncoder::new(BufWriter::new(file), XZ_PRESET);
+    let mut ar = tar::Builder::new(encoder);
     ar.follow_symlinks(false);
-    let mut dirs = vec![];
-    let mut files = vec![];
-    let nmk_home_path = nmk_home.path().as_path();
-    for name in BACKUP_PATHS {
-        let path = nmk_home_path.join(name);
-        if path.is_dir() {
-            if should_backup_dir(&path) {
-                dirs.push((name, path));
-            }
-        } else if path.is_file() {
-            if let Ok(f) = File::open(&path) {
-                files.push((name, f))
+
+    let manifest = toml::to_string_pretty(&Manifest::new(files))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+    );
+    header.set_cksum();
+    ar.append_data(&mut header, MANIFEST, manifest.as_bytes())?;
+
+    for name in files {
+        ar.append_path_with_name(nmk_path.join(name), name)?;
+        debug!("Added: {}", name.display());
+    }
+    ar.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+/// Delete oldest archives with given prefix so that at most `keep` archives remain
+pub fn prune(output_dir: &Path, prefix: &str, keep: usize) -> io::Result<Vec<PathBuf>> {
+    if keep == 0 || !output_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut archives = Vec::new();
+    for entry in fs::read_dir(output_dir)? {
+        let entry = entry?;
+        if let Ok(name) = entry.file_name().into_string() {
+            if is_archive_name(&name, prefix) {
+                archives.push((name, entry.path()));
             }
         }
     }
-    for (name, d) in dirs {
-        ar.append_dir_all(name, d)?;
-        debug!("Added dir: {}", name);
-    }
-    for (name, mut f) in files {
-        ar.append_file(name, &mut f)?;
-        debug!("Added file: {}", name);
+    archives
+        .sort_by(|(a, _), (b, _)| archive_sort_key(a, prefix).cmp(&archive_sort_key(b, prefix)));
+    let excess = archives.len().saturating_sub(keep);
+    let removed: Vec<_> = archives.drain(..excess).map(|(_, p)| p).collect();
+    for p in &removed {
+        fs::remove_file(p)?;
+        debug!("Removed old backup {}", p.display());
     }
-    ar.finish()?;
+    Ok(removed)
+}
+
+pub fn backup_files(
+    nmk_home: &NmkHome,
+    config: &BackupConfig,
+    output_dir: &Path,
+) -> crate::Result<PathBuf> {
+    let nmk_path = nmk_home.path().as_path();
+    let files = collect_files(nmk_path, &config.include, &config.exclude)?;
+    let ar_path = create_archive(nmk_path, &files, output_dir, ARCHIVE_PREFIX)?;
+    prune(output_dir, ARCHIVE_PREFIX, config.keep)?;
     info!("Important files are backup to {}", ar_path.display());
-    Ok(())
+    Ok(ar_path)
+}
+
+/// Snapshot files that dotfiles update is going to touch
+///
+/// This includes locally modified dotfiles, which are going to be deleted by
+/// [`dotfiles::uninstall`], and configured backup files. Return `None` if there is nothing to save.
+pub fn snapshot_before_update(
+    nmk_home: &NmkHome,
+    config: &BackupConfig,
+) -> crate::Result<Option<PathBuf>> {
+    let nmk_path = nmk_home.path();
+    let mut files: BTreeSet<_> = dotfiles::modified_files(nmk_path)?.into_iter().collect();
+    for p in &files {
+        debug!("Modified since installed: {}", p.display());
+    }
+    files.extend(collect_files(
+        nmk_path.as_path(),
+        &config.include,
+        &config.exclude,
+    )?);
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let files: Vec<_> = files.into_iter().collect();
+    let output_dir = config.output_dir();
+    let ar_path = create_archive(nmk_path.as_path(), &files, &output_dir, SNAPSHOT_PREFIX)?;
+    prune(&output_dir, SNAPSHOT_PREFIX, config.snapshot_keep)?;
+    Ok(Some(ar_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(
+            literal_prefix("zsh/completion/"),
+            Path::new("zsh/completion")
+        );
+        assert_eq!(literal_prefix("zsh/*.extra"), Path::new("zsh"));
+        assert_eq!(literal_prefix("**/.empty"), Path::new(""));
+        assert_eq!(
+            literal_prefix("./.tmux_history"),
+            Path::new(".tmux_history")
+        );
+    }
+
+    #[test]
+    fn test_collect_and_prune() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let home = root.join("home");
+        fs::create_dir_all(home.join("zsh/zshrc.extra.d")).unwrap();
+        fs::write(home.join(".tmux_history"), "ls").unwrap();
+        fs::write(home.join("zsh/zshrc.extra.d/.empty"), "").unwrap();
+        fs::write(home.join("zsh/zshrc.extra.d/a.zsh"), "").unwrap();
+        fs::write(home.join("zsh/zshrc.extra.d/b.bak"), "").unwrap();
+
+        let include = strings(&[".tmux_history", "zsh/zshrc.extra.d/", "missing/*"]);
+        let exclude = strings(&["**/.empty", "**/*.bak"]);
+        let files = collect_files(&home, &include, &exclude).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from(".tmux_history"),
+                PathBuf::from("zsh/zshrc.extra.d/a.zsh")
+            ]
+        );
+
+        let out = root.join("out");
+        fs::create_dir_all(&out).unwrap();
+        for time in &["20210101T000000Z", "20210102T000000Z", "20210103T000000Z"] {
+            let name = format!("{}{}{}", ARCHIVE_PREFIX, time, ARCHIVE_SUFFIX);
+            fs::write(out.join(name), "").unwrap();
+        }
+        fs::write(out.join("unrelated.tar.xz"), "").unwrap();
+        let removed = prune(&out, ARCHIVE_PREFIX, 2).unwrap();
+        assert_eq!(
+            removed,
+            vec![out.join("nmk-backup-20210101T000000Z.tar.xz")]
+        );
+        assert!(out.join("unrelated.tar.xz").exists());
+
+        let ar_path = create_archive(&home, &files, &out, ARCHIVE_PREFIX).unwrap();
+        let decoder = xz2::read::XzDecoder::new(File::open(&ar_path).unwrap());
+        let names: Vec<_> = tar::Archive::new(decoder)
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().into_owned())
+            .collect();
+        assert_eq!(names[0], Path::new(MANIFEST));
+        assert_eq!(&names[1..], &files[..]);
+
+        let time = DateTime::from_unix_seconds(0);
+        let names: Vec<_> = [0, 1, 99, 100]
+            .iter()
+            .map(|&counter| archive_name(ARCHIVE_PREFIX, time, counter))
+            .chain(Some("nmk-backup-19700101T000001Z.tar.xz".to_owned()))
+            .collect();
+        let keys: Vec<_> = names
+            .iter()
+            .map(|n| archive_sort_key(n, ARCHIVE_PREFIX))
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        let second = create_archive(&home, &files, &out, ARCHIVE_PREFIX).unwrap();
+        assert_ne!(second, ar_path);
+        assert!(ar_path.exists());
+
+        let missing = vec![PathBuf::from("missing")];
+        assert!(create_archive(&home, &missing, &out, ARCHIVE_PREFIX).is_err());
+        assert!(fs::read_dir(&out).unwrap().all(|e| !e
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(".partial-")));
+    }
 }
@@ -0,0 +1,335 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::consts::bin::TMUX;
+use crate::probe_cache;
+
+/// Program that moves text between tmux and system clipboard
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    PartialEq,
+    strum::AsStaticStr,
+    strum::EnumString,
+    strum::EnumVariantNames,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Backend {
+    /// macOS `pbcopy` and `pbpaste`
+    Pbcopy,
+    /// Wayland `wl-copy` and `wl-paste`, needs `WAYLAND_DISPLAY`
+    WlCopy,
+    /// X11 `xclip`, needs `DISPLAY`
+    Xclip,
+    /// X11 `xsel`, needs `DISPLAY`
+    Xsel,
+    /// `win32yank.exe` from WSL, keeps line endings intact
+    Win32yank,
+    /// `clip.exe` and `powershell.exe` from WSL
+    Wsl,
+    /// OSC 52 escape sequence, interpreted by terminal so it works over SSH
+    Osc52,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// Backends in order of preference, the first available one is used
+    pub backends: Vec<Backend>,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        ClipboardConfig {
+            backends: vec![
+                Backend::Pbcopy,
+                Backend::WlCopy,
+                Backend::Xclip,
+                Backend::Xsel,
+                Backend::Win32yank,
+                Backend::Wsl,
+                Backend::Osc52,
+            ],
+        }
+    }
+}
+
+impl ClipboardConfig {
+    /// Let tmux forward copied text to terminal with OSC 52
+    pub fn uses_osc52(&self) -> bool {
+        self.backends.contains(&Backend::Osc52)
+    }
+
+    /// First backend that works in current environment
+    pub fn select(&self) -> Option<Backend> {
+        self.available().next()
+    }
+
+    /// Backends that work in current environment in order of preference
+    pub fn available(&self) -> impl Iterator<Item = Backend> + '_ {
+        self.backends.iter().copied().filter(|b| b.is_available())
+    }
+}
+
+#[derive(Debug)]
+pub enum ClipboardError {
+    /// None of configured backends is available
+    NoBackend,
+    /// Backend can't read clipboard
+    PasteUnsupported(Backend),
+    Io(io::Error),
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipboardError::NoBackend => write!(f, "no clipboard backend available"),
+            ClipboardError::PasteUnsupported(b) => {
+                write!(f, "{} backend can't paste outside tmux", b.as_str())
+            }
+            ClipboardError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClipboardError {}
+
+impl From<io::Error> for ClipboardError {
+    fn from(e: io::Error) -> Self {
+        ClipboardError::Io(e)
+    }
+}
+
+fn has_env(key: &str) -> bool {
+    env::var_os(key).is_some_and(|v| !v.is_empty())
+}
+
+fn has_bin(bin: &str) -> bool {
+    probe_cache::which(bin).is_some()
+}
+
+fn is_wsl() -> bool {
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+}
+
+fn is_inside_tmux() -> bool {
+    has_env("TMUX")
+}
+
+impl Backend {
+    pub fn as_str(&self) -> &'static str {
+        strum::AsStaticRef::as_static(self)
+    }
+
+    /// Check environment and binaries, this is done when copying, not when rendering config
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Pbcopy => has_bin("pbcopy"),
+            Backend::WlCopy => has_env("WAYLAND_DISPLAY") && has_bin("wl-copy"),
+            Backend::Xclip => has_env("DISPLAY") && has_bin("xclip"),
+            Backend::Xsel => has_env("DISPLAY") && has_bin("xsel"),
+            Backend::Win32yank => has_bin("win32yank.exe"),
+            Backend::Wsl => is_wsl() && has_bin("clip.exe") && has_bin("powershell.exe"),
+            Backend::Osc52 => true,
+        }
+    }
+
+    fn copy_command(self) -> Option<&'static [&'static str]> {
+        let args: &[&str] = match self {
+            Backend::Pbcopy => &["pbcopy"],
+            Backend::WlCopy => &["wl-copy"],
+            Backend::Xclip => &["xclip", "-selection", "clipboard"],
+            Backend::Xsel => &["xsel", "--clipboard", "--input"],
+            Backend::Win32yank => &["win32yank.exe", "-i", "--crlf"],
+            Backend::Wsl => &["clip.exe"],
+            Backend::Osc52 => return None,
+        };
+        Some(args)
+    }
+
+    fn paste_command(self) -> Option<&'static [&'static str]> {
+        let args: &[&str] = match self {
+            Backend::Pbcopy => &["pbpaste"],
+            Backend::WlCopy => &["wl-paste", "--no-newline"],
+            Backend::Xclip => &["xclip", "-selection", "clipboard", "-o"],
+            Backend::Xsel => &["xsel", "--clipboard", "--output"],
+            Backend::Win32yank => &["win32yank.exe", "-o", "--lf"],
+            Backend::Wsl => &["powershell.exe", "-NoProfile", "-Command", "Get-Clipboard"],
+            Backend::Osc52 => return None,
+        };
+        Some(args)
+    }
+
+    pub fn copy(self, data: &[u8]) -> Result<(), ClipboardError> {
+        match self.copy_command() {
+            Some(args) => pipe_to(args, data),
+            None if is_inside_tmux() => copy_tmux_buffer(data),
+            None => write_osc52(data),
+        }
+    }
+
+    pub fn paste(self) -> Result<Vec<u8>, ClipboardError> {
+        match self.paste_command() {
+            Some(args) => read_from(args),
+            // with set-clipboard on, tmux buffer is what was sent to terminal
+            None if is_inside_tmux() => read_from(&[TMUX, "save-buffer", "-"]),
+            None => Err(ClipboardError::PasteUnsupported(self)),
+        }
+    }
+}
+
+fn command_error(args: &[&str], status: std::process::ExitStatus) -> ClipboardError {
+    ClipboardError::Io(io::Error::other(format!(
+        "{} exit with status code: {:?}",
+        args[0],
+        status.code()
+    )))
+}
+
+fn pipe_to(args: &[&str], data: &[u8]) -> Result<(), ClipboardError> {
+    let mut child = Command::new(args[0])
+        .args(&args[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data)?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(command_error(args, status))
+    }
+}
+
+fn read_from(args: &[&str]) -> Result<Vec<u8>, ClipboardError> {
+    let output = Command::new(args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(command_error(args, output.status))
+    }
+}
+
+/// Load into tmux buffer, `-w` asks tmux 3.2+ to also send it to terminal with OSC 52
+fn copy_tmux_buffer(data: &[u8]) -> Result<(), ClipboardError> {
+    pipe_to(&[TMUX, "load-buffer", "-w", "-"], data)
+        .or_else(|_| pipe_to(&[TMUX, "load-buffer", "-"], data))
+}
+
+fn write_osc52(data: &[u8]) -> Result<(), ClipboardError> {
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+    tty.write_all(osc52_sequence(data).as_bytes())?;
+    Ok(())
+}
+
+/// Escape sequence that sets clipboard selection `c` to `data`
+pub fn osc52_sequence(data: &[u8]) -> String {
+    format!("\x1b]52;c;{}\x07", base64(data))
+}
+
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Run `f` with available backends in order until one succeeds, return the last error if all fail
+///
+/// Availability only checks environment, e.g. xclip fails when `DISPLAY` is left from a closed X session.
+fn try_backends<T, F>(config: &ClipboardConfig, f: F) -> Result<(Backend, T), ClipboardError>
+where
+    F: Fn(Backend) -> Result<T, ClipboardError>,
+{
+    let mut last_error = ClipboardError::NoBackend;
+    for backend in config.available() {
+        match f(backend) {
+            Ok(value) => return Ok((backend, value)),
+            Err(e) => {
+                debug!("Clipboard backend {} failed: {}", backend.as_str(), e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Copy `data` with first available backend that succeeds, return the backend used
+pub fn copy(config: &ClipboardConfig, data: &[u8]) -> Result<Backend, ClipboardError> {
+    try_backends(config, |backend| backend.copy(data)).map(|(backend, _)| backend)
+}
+
+/// Read clipboard with first available backend that succeeds
+pub fn paste(config: &ClipboardConfig) -> Result<Vec<u8>, ClipboardError> {
+    try_backends(config, Backend::paste).map(|(_, data)| data)
+}
+
+/// Read all of stdin, used by `nmk clipboard copy` when tmux pipes selection
+pub fn read_stdin() -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    io::stdin().read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config: ClipboardConfig =
+            toml::from_str(r#"backends = ["wl-copy", "win32yank", "osc52"]"#).unwrap();
+        assert_eq!(
+            config.backends,
+            vec![Backend::WlCopy, Backend::Win32yank, Backend::Osc52]
+        );
+        assert!(config.uses_osc52());
+        assert_eq!("wl-copy".parse::<Backend>().unwrap(), Backend::WlCopy);
+        assert_eq!(Backend::Osc52.as_str(), "osc52");
+        let empty = ClipboardConfig { backends: vec![] };
+        assert_eq!(empty.select(), None);
+        assert!(!empty.uses_osc52());
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(osc52_sequence(b"hi"), "\x1b]52;c;aGk=\x07");
+    }
+}
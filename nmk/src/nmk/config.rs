@@ -1,3 +1,76 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::backup::BackupConfig;
+use crate::clipboard::ClipboardConfig;
+use crate::history::HistoryConfig;
+use crate::home::NmkPath;
+use crate::tmux::keys::KeysConfig;
+use crate::tmux::nested::NestedConfig;
+use crate::tmux::profile::Profile;
+use crate::tmux::session::SessionConfig;
+use crate::tmux::status::StatusConfig;
+use crate::tmux::validate::ValidateMode;
+
+/// User configuration read from `$NMK_HOME/nmk.toml`
+///
+/// Every table is optional, missing values fallback to defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub backup: BackupConfig,
+    pub clipboard: ClipboardConfig,
+    pub history: HistoryConfig,
+    pub keys: KeysConfig,
+    pub nested: NestedConfig,
+    pub profiles: BTreeMap<String, Profile>,
+    pub session: SessionConfig,
+    /// Default tmux socket name, may contain placeholders such as `{host}`
+    pub socket: Option<String>,
+    pub status: StatusConfig,
+    /// Name of builtin theme or file in `$NMK_HOME/themes`
+    pub theme: Option<String>,
+    /// Check rendered tmux config with throwaway tmux server before starting tmux
+    pub validate: ValidateMode,
+}
+
+impl Config {
+    pub fn load(nmk_path: &NmkPath) -> crate::Result<Self> {
+        let path = nmk_path.config();
+        if !path.exists() {
+            debug!("Not found {}, using default config", path.display());
+            return Ok(Config::default());
+        }
+        let contents = fs::read_to_string(&path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+}
+
+/// Set top-level `key` of TOML document to string `value`, comments and other lines are kept
+pub fn set_top_level_string(contents: &str, key: &str, value: &str) -> String {
+    let line = format!("{} = {}", key, toml::Value::String(value.to_string()));
+    let mut lines: Vec<&str> = contents.lines().collect();
+    let top_level = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top_level].iter().position(|l| {
+        l.trim_start()
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    });
+    match existing {
+        Some(index) => lines[index] = &line,
+        None => lines.insert(0, &line),
+    }
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
 #[inline(always)]
 pub fn one_hot(val: bool) -> &'static str {
     if val {
@@ -15,3 +88,55 @@ pub fn on_off(val: bool) -> &'static str {
         "off"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.backup.keep, 10);
+        let config: Config = toml::from_str(
+            r#"
+            [backup]
+            include = ["zsh/zshrc.extra.d/"]
+            keep = 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.backup.include, vec!["zsh/zshrc.extra.d/"]);
+        assert_eq!(config.backup.exclude, vec!["**/.empty"]);
+        assert_eq!(config.backup.keep, 3);
+
+        let config: Config = toml::from_str(
+            r#"
+            [profiles.work]
+            socket = "work"
+            start_directory = "~/work"
+
+            [[profiles.work.windows]]
+            panes = [{ command = "htop" }, { split = "horizontal" }]
+            "#,
+        )
+        .unwrap();
+        let profile = &config.profiles["work"];
+        assert_eq!(profile.socket.as_deref(), Some("work"));
+        assert_eq!(profile.layout.session_name("work"), "work");
+        assert_eq!(profile.layout.windows[0].panes.len(), 2);
+    }
+
+    #[test]
+    fn test_set_top_level_string() {
+        let contents = "# my config\ntheme = \"nord\"\n\n[profiles.work]\ntheme = \"x\"\n";
+        let actual = set_top_level_string(contents, "theme", "gruvbox");
+        assert_eq!(
+            actual,
+            "# my config\ntheme = \"gruvbox\"\n\n[profiles.work]\ntheme = \"x\"\n"
+        );
+        let actual = set_top_level_string("[backup]\nkeep = 3\n", "theme", "nord");
+        assert_eq!(actual, "theme = \"nord\"\n[backup]\nkeep = 3\n");
+        let config: Config = toml::from_str(&actual).unwrap();
+        assert_eq!(config.theme.as_deref(), Some("nord"));
+    }
+}
@@ -9,9 +9,11 @@ declare!(EDITOR);
 declare!(LD_LIBRARY_PATH);
 declare!(NMK_BIN);
 declare!(NMK_HOME);
+declare!(NMK_NESTED);
 declare!(NMK_START_MODE);
 declare!(NMK_TMUX_VERSION);
 declare!(NMK_ZSH_GLOBAL_RCS);
 declare!(PATH);
+declare!(TERMINFO_DIRS);
 declare!(VIMINIT);
 declare!(ZDOTDIR);
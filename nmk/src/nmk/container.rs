@@ -1,6 +1,7 @@
 use std::fs;
 
 use crate::platform;
+use crate::probe_cache;
 
 struct CGroup<'a> {
     control_group: &'a str,
@@ -35,13 +36,55 @@ fn is_container(s: &str) -> bool {
         .any(|cg| cg.is_container())
 }
 
+/// Id of container from control group path, e.g. `/docker/<id>` or `/kubepods/.../<id>`
+fn container_id(s: &str) -> Option<&str> {
+    s.lines()
+        .flat_map(CGroup::parse)
+        .filter(|cg| cg.is_container())
+        .find_map(|cg| cg.control_group.rsplit('/').next())
+        .filter(|id| !id.is_empty())
+}
+
+/// Namespaces that differ between containers, cgroup of a process is the same as its
+/// parent's unless it's moved to another container
+fn namespace_key() -> Option<String> {
+    let links = ["/proc/self/ns/cgroup", "/proc/self/ns/mnt"]
+        .iter()
+        .map(|path| Some(fs::read_link(path).ok()?.to_string_lossy().into_owned()))
+        .collect::<Option<Vec<_>>>()?;
+    Some(links.join(" "))
+}
+
+fn read_self_cgroup() -> String {
+    let self_cgroup = format!("/proc/{}/cgroup", std::process::id());
+    let read = || fs::read_to_string(&self_cgroup).ok();
+    let contents = match namespace_key() {
+        Some(key) => probe_cache::with_shared(|cache| cache.get_or_probe("cgroup", key, read)),
+        None => read(),
+    };
+    contents.expect("cannot read self cgroup")
+}
+
+/// Short name of container we are running in, `None` if not containerized
+///
+/// Docker uses the first 12 characters of id as default hostname, we do the same.
+/// If id is not visible in control group, e.g. cgroup v2 namespace, hostname is used.
+pub fn container_name() -> Option<String> {
+    if !is_containerized() {
+        return None;
+    }
+    let contents = read_self_cgroup();
+    match container_id(&contents) {
+        Some(id) => Some(id.chars().take(12).collect()),
+        None => Some(platform::hostname()),
+    }
+}
+
 pub fn is_containerized() -> bool {
     if platform::is_mac() {
         return false;
     }
-    let self_cgroup = format!("/proc/{}/cgroup", std::process::id());
-    let contents = fs::read_to_string(self_cgroup).expect("cannot read self cgroup");
-    is_container(&contents)
+    is_container(&read_self_cgroup())
 }
 
 #[cfg(test)]
@@ -68,11 +111,16 @@ mod tests {
 11:perf_event:/docker/c6fa62a9938149f6098fd0cdaffc9cdf0f526f25d97b5f6e2a4cc1fccc7f7ce1
 10:rdma:/"#;
         assert!(is_container(docker_cgroup));
+        assert_eq!(
+            container_id(docker_cgroup),
+            Some("c6fa62a9938149f6098fd0cdaffc9cdf0f526f25d97b5f6e2a4cc1fccc7f7ce1")
+        );
         let init_cgroup = r#"
 12:cpu,cpuacct:/
 11:perf_event:/
 0::/init.scope"#;
         assert!(!is_container(init_cgroup));
+        assert_eq!(container_id(init_cgroup), None);
         let k8s_cgroup = r#"
 12:hugetlb:/kubepods/besteffort/poda00e29fd-7bbd-11e9-8679-fa163ea7e3b8/c4b1403f3d9c7ce261be851df71d9a9773c53419075ccda39ae8fe6a39fd2eb1
 11:cpuset:/kubepods/besteffort/poda00e29fd-7bbd-11e9-8679-fa163ea7e3b8/c4b1403f3d9c7ce261be851df71d9a9773c53419075ccda39ae8fe6a39fd2eb1"#;
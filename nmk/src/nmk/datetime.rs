@@ -0,0 +1,85 @@
+use std::fmt::{self, Display};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAY_SECONDS: u64 = 24 * 60 * 60;
+
+/// A UTC calendar date and time with second precision
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct DateTime {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    pub fn now() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self::from_unix_seconds(secs)
+    }
+
+    /// Convert seconds since unix epoch to calendar date
+    ///
+    /// This is `civil_from_days` from http://howardhinnant.github.io/date_algorithms.html
+    pub fn from_unix_seconds(secs: u64) -> Self {
+        let days = secs / DAY_SECONDS;
+        let secs_of_day = secs % DAY_SECONDS;
+        // shift epoch from 1970-01-01 to 0000-03-01
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + u64::from(month <= 2);
+        DateTime {
+            year: year as u32,
+            month: month as u32,
+            day: day as u32,
+            hour: (secs_of_day / 3600) as u32,
+            minute: (secs_of_day / 60 % 60) as u32,
+            second: (secs_of_day % 60) as u32,
+        }
+    }
+
+    /// Compact form that is safe to use in file name and sorts chronologically, e.g. `20210630T142501Z`
+    pub fn to_compact(self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// RFC 3339 format, e.g. `2021-06-30T14:25:01Z`
+impl Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_unix_seconds() {
+        let actual = DateTime::from_unix_seconds(0);
+        assert_eq!(actual.to_string(), "1970-01-01T00:00:00Z");
+        let actual = DateTime::from_unix_seconds(951_782_400);
+        assert_eq!(actual.to_string(), "2000-02-29T00:00:00Z");
+        let actual = DateTime::from_unix_seconds(1_625_063_101);
+        assert_eq!(actual.to_string(), "2021-06-30T14:25:01Z");
+        assert_eq!(actual.to_compact(), "20210630T142501Z");
+    }
+}
@@ -31,7 +31,11 @@ fn strip_components(path: &Path, n: usize) -> &Path {
     components.as_path()
 }
 
-pub fn uninstall(nmk_path: &NmkPath) -> crate::Result<()> {
+/// Call `f` with relative and full path of every file in installed files list
+fn for_each_installed_file<F>(nmk_path: &NmkPath, mut f: F) -> crate::Result<()>
+where
+    F: FnMut(&str, &Path) -> crate::Result<()>,
+{
     let installed_files_list = nmk_path.dotfiles_file_list();
     // It is easier to read the whole file as string.
     // But we use this low level algorithm to show how rust can handle it with smallest
@@ -56,17 +60,49 @@ pub fn uninstall(nmk_path: &NmkPath) -> crate::Result<()> {
         }
         path_buf = set_base_path(path_buf, nmk_path);
         path_buf.push(file_path);
-        match fs::remove_file(&path_buf) {
+        f(file_path, &path_buf)?;
+    }
+    Ok(())
+}
+
+pub fn uninstall(nmk_path: &NmkPath) -> crate::Result<()> {
+    for_each_installed_file(nmk_path, |_, path| {
+        match fs::remove_file(path) {
             Ok(_) => {
-                debug!("Removed {}", path_buf.display());
+                debug!("Removed {}", path.display());
             }
             Err(err) if err.kind() == ErrorKind::NotFound => {
-                warn!("Not found: {}", path_buf.display())
+                warn!("Not found: {}", path.display())
             }
             err => err?,
         }
-    }
-    Ok(())
+        Ok(())
+    })
+}
+
+/// Find installed files that have been changed since installation
+///
+/// Extracted files keep modification time from the archive, so anything newer than
+/// dotfiles metadata (written right after extraction) or installed files list is a local change.
+pub fn modified_files(nmk_path: &NmkPath) -> crate::Result<Vec<PathBuf>> {
+    let reference = [nmk_path.dotfiles_meta(), nmk_path.dotfiles_file_list()]
+        .iter()
+        .find_map(|p| p.metadata().and_then(|m| m.modified()).ok());
+    let installed_at = match reference {
+        Some(t) => t,
+        None => return Ok(Vec::new()),
+    };
+    let mut modified = Vec::new();
+    for_each_installed_file(nmk_path, |relative, path| {
+        // symlinks are part of dotfiles, there is no point to check them
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if metadata.is_file() && metadata.modified()? > installed_at {
+                modified.push(PathBuf::from(relative));
+            }
+        }
+        Ok(())
+    })?;
+    Ok(modified)
 }
 
 fn set_base_path(path_buf: PathBuf, dst: &NmkPath) -> PathBuf {
@@ -75,3 +111,42 @@ fn set_base_path(path_buf: PathBuf, dst: &NmkPath) -> PathBuf {
     s.push(dst);
     s.into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_first_component() {
+        let p = Path::new(".nmk/bin/nmk");
+        let actual = strip_components(p, 1);
+        assert_eq!(actual, Path::new("bin/nmk"));
+        let actual = strip_components(p, 2);
+        assert_eq!(actual, Path::new("nmk"));
+    }
+
+    #[test]
+    fn test_modified_files() {
+        use nix::sys::stat::utimes;
+        use nix::sys::time::{TimeVal, TimeValLike};
+
+        let tmp = tempfile::tempdir().unwrap();
+
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("zsh")).unwrap();
+        let nmk_path = NmkPath::new(&dir);
+        let installed_at = TimeVal::seconds(1_600_000_000);
+        let edited_at = TimeVal::seconds(1_600_000_100);
+        for name in &["zsh/zshrc", "zsh/zshenv"] {
+            fs::write(dir.join(name), "").unwrap();
+            utimes(&dir.join(name), &installed_at, &installed_at).unwrap();
+        }
+        utimes(&dir.join("zsh/zshrc"), &edited_at, &edited_at).unwrap();
+        let list = nmk_path.dotfiles_file_list();
+        fs::write(&list, "./\0./zsh/zshrc\0./zsh/zshenv\0./zsh/missing\0").unwrap();
+        utimes(&list, &installed_at, &installed_at).unwrap();
+
+        let actual = modified_files(nmk_path).unwrap();
+        assert_eq!(actual, vec![PathBuf::from("zsh/zshrc")]);
+    }
+}
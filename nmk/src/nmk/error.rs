@@ -55,8 +55,12 @@ impl Error {
     }
 }
 
+impl_from_error!(crate::clipboard::ClipboardError);
+impl_from_error!(glob::PatternError);
 impl_from_error!(serde_json::Error);
 impl_from_error!(std::io::Error);
 impl_from_error!(std::str::Utf8Error);
+impl_from_error!(crate::tmux::control::ControlError);
+impl_from_error!(toml::de::Error);
 impl_from_error!(toml::ser::Error);
 impl_from_error!(ureq::Error);
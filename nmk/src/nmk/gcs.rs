@@ -38,6 +38,7 @@ impl ObjectMeta {
     }
 }
 
+#[allow(clippy::result_large_err)]
 pub fn download_file(media_link: &str) -> Result<impl Read + Send, ureq::Error> {
     Ok(ureq::get(media_link).call()?.into_reader())
 }
@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+
+use crate::home::NmkPath;
+
+const SECRET_PATTERNS: &[&str] = &[
+    "*api_key*",
+    "*apikey*",
+    "*passwd*",
+    "*password*",
+    "*secret*",
+    "*token=*",
+    "*Authorization:*",
+];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Glob patterns, matched case-insensitively, of commands that must not be merged
+    pub secret_patterns: Vec<String>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            secret_patterns: SECRET_PATTERNS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, strum::EnumString, strum::EnumVariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    /// zsh extended history, `: <timestamp>:<duration>;<command>`
+    Zsh,
+    /// tmux command prompt history, one command per line
+    Tmux,
+}
+
+impl Format {
+    /// Guess format from file name and content
+    pub fn detect(path: &Path, contents: &[u8]) -> Self {
+        let is_tmux_name = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().contains("tmux"));
+        if is_tmux_name {
+            return Format::Tmux;
+        }
+        let first_line = contents
+            .split(|&b| b == b'\n')
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        if parse_extended_header(first_line).is_some() {
+            Format::Zsh
+        } else {
+            Format::Tmux
+        }
+    }
+
+    /// Default history file of this format
+    pub fn nmk_history_file(self, nmk_path: &NmkPath) -> PathBuf {
+        match self {
+            Format::Zsh => nmk_path.zsh().join(".zsh_history"),
+            Format::Tmux => nmk_path.as_path().join(".tmux_history"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    /// Seconds since unix epoch, 0 if unknown
+    pub timestamp: u64,
+    pub duration: u64,
+    /// Raw command, lines of multi-line command are joined by `\` + newline as zsh does
+    ///
+    /// zsh metafies non-ASCII characters in history file, so we keep bytes untouched.
+    pub command: Vec<u8>,
+}
+
+/// Parse `: <timestamp>:<duration>;` and return timestamp, duration and command
+fn parse_extended_header(line: &[u8]) -> Option<(u64, u64, &[u8])> {
+    let rest = line.strip_prefix(b": ")?;
+    let semicolon = rest.iter().position(|&b| b == b';')?;
+    let (header, command) = (&rest[..semicolon], &rest[semicolon + 1..]);
+    let header = std::str::from_utf8(header).ok()?;
+    let mut iter = header.splitn(2, ':');
+    let timestamp = iter.next()?.trim().parse().ok()?;
+    let duration = iter.next()?.trim().parse().ok()?;
+    Some((timestamp, duration, command))
+}
+
+pub fn parse_zsh(contents: &[u8]) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut continuation = false;
+    for line in contents.split(|&b| b == b'\n') {
+        if continuation {
+            if let Some(last) = entries.last_mut() {
+                last.command.push(b'\n');
+                last.command.extend_from_slice(line);
+            }
+        } else if let Some((timestamp, duration, command)) = parse_extended_header(line) {
+            entries.push(Entry {
+                timestamp,
+                duration,
+                command: command.to_vec(),
+            });
+        } else if !line.is_empty() {
+            // history without timestamp, assume it happened at the same time as previous one
+            let timestamp = entries.last().map_or(0, |e| e.timestamp);
+            entries.push(Entry {
+                timestamp,
+                duration: 0,
+                command: line.to_vec(),
+            });
+        }
+        continuation = line.ends_with(b"\\");
+    }
+    entries
+}
+
+pub fn parse_tmux(contents: &[u8]) -> Vec<Entry> {
+    contents
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| Entry {
+            timestamp: 0,
+            duration: 0,
+            command: line.to_vec(),
+        })
+        .collect()
+}
+
+pub fn parse(format: Format, contents: &[u8]) -> Vec<Entry> {
+    match format {
+        Format::Zsh => parse_zsh(contents),
+        Format::Tmux => parse_tmux(contents),
+    }
+}
+
+pub fn compile_patterns(patterns: &[String]) -> crate::Result<Vec<Pattern>> {
+    patterns.iter().map(|p| Ok(Pattern::new(p)?)).collect()
+}
+
+fn is_secret(entry: &Entry, patterns: &[Pattern]) -> bool {
+    let command = String::from_utf8_lossy(&entry.command);
+    patterns
+        .iter()
+        .any(|p| p.matches_with(&command, MATCH_OPTIONS))
+}
+
+/// Merge entries by timestamp, only the latest occurrence of each command is kept
+///
+/// Sorting is stable, so entries without timestamp keep their input order.
+pub fn merge(sources: Vec<Vec<Entry>>, secret_patterns: &[Pattern]) -> Vec<Entry> {
+    let mut entries: Vec<_> = sources.into_iter().flatten().collect();
+    entries.sort_by_key(|e| e.timestamp);
+    let mut seen = HashSet::new();
+    let mut merged: Vec<_> = entries
+        .into_iter()
+        .rev()
+        .filter(|e| !is_secret(e, secret_patterns))
+        .filter(|e| seen.insert(e.command.clone()))
+        .collect();
+    merged.reverse();
+    merged
+}
+
+pub fn write(format: Format, entries: &[Entry]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for e in entries {
+        if format == Format::Zsh {
+            buf.extend_from_slice(format!(": {}:{};", e.timestamp, e.duration).as_bytes());
+        }
+        buf.extend_from_slice(&e.command);
+        buf.push(b'\n');
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZSH_A: &[u8] =
+        b": 1600000000:0;ls\n: 1600000010:2;echo foo \\\nbar\n: 1600000020:0;git status\n";
+    const ZSH_B: &[u8] =
+        b": 1600000005:0;cd /tmp\n: 1600000030:0;ls\n: 1600000040:0;export GITHUB_TOKEN=abc\n";
+
+    #[test]
+    fn test_parse_zsh() {
+        let entries = parse_zsh(ZSH_A);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].timestamp, 1_600_000_010);
+        assert_eq!(entries[1].duration, 2);
+        assert_eq!(entries[1].command, b"echo foo \\\nbar");
+        assert_eq!(write(Format::Zsh, &entries), ZSH_A);
+    }
+
+    #[test]
+    fn test_merge() {
+        let patterns = compile_patterns(&HistoryConfig::default().secret_patterns).unwrap();
+        let merged = merge(vec![parse_zsh(ZSH_A), parse_zsh(ZSH_B)], &patterns);
+        let commands: Vec<_> = merged
+            .iter()
+            .map(|e| String::from_utf8_lossy(&e.command).into_owned())
+            .collect();
+        assert_eq!(
+            commands,
+            vec!["cd /tmp", "echo foo \\\nbar", "git status", "ls"]
+        );
+    }
+
+    #[test]
+    fn test_merge_tmux() {
+        let merged = merge(
+            vec![
+                parse_tmux(b"kill-server\nsplit-window\n"),
+                parse_tmux(b"kill-server\n"),
+            ],
+            &[],
+        );
+        assert_eq!(write(Format::Tmux, &merged), b"split-window\nkill-server\n");
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(Format::detect(Path::new("a"), ZSH_A), Format::Zsh);
+        assert_eq!(
+            Format::detect(Path::new(".tmux_history"), ZSH_A),
+            Format::Tmux
+        );
+        assert_eq!(
+            Format::detect(Path::new("a"), b"new-window\n"),
+            Format::Tmux
+        );
+    }
+}
@@ -17,6 +17,16 @@ impl fmt::Debug for NmkHome {
     }
 }
 
+/// Expand leading `~` to home directory
+pub fn expand_tilde(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir()
+            .expect("failed to find home directory")
+            .join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
 fn default_nmk_home() -> Option<PathBuf> {
     home_dir().map(|p| p.join(".nmk"))
 }
@@ -70,7 +80,7 @@ pub struct NmkPath {
 }
 
 impl NmkPath {
-    fn new<P: AsRef<Path> + ?Sized>(p: &P) -> &Self {
+    pub(crate) fn new<P: AsRef<Path> + ?Sized>(p: &P) -> &Self {
         // SAFETY: Self is new type struct with same layout and representation as inner Path
         unsafe { &*(p.as_ref() as *const Path as *const NmkPath) }
     }
@@ -83,6 +93,11 @@ impl NmkPath {
         self.inner.join("bin")
     }
 
+    /// User configuration file, it is not part of dotfiles so updates never overwrite it
+    pub fn config(&self) -> PathBuf {
+        self.inner.join("nmk.toml")
+    }
+
     pub fn dotfiles_file_list(&self) -> PathBuf {
         self.inner.join(".installed-files")
     }
@@ -99,6 +114,26 @@ impl NmkPath {
         self.inner.join(".nmk.meta")
     }
 
+    /// Directory of layout files used by `nmk open`
+    pub fn layouts(&self) -> PathBuf {
+        self.inner.join("layouts")
+    }
+
+    /// Saved tmux sessions
+    pub fn sessions(&self) -> PathBuf {
+        self.inner.join("sessions")
+    }
+
+    /// Terminfo entries installed by nmk, see [`crate::terminfo::install_bundled`]
+    pub fn terminfo(&self) -> PathBuf {
+        self.inner.join("terminfo")
+    }
+
+    /// User themes, see [`crate::tmux::theme`]
+    pub fn themes(&self) -> PathBuf {
+        self.inner.join("themes")
+    }
+
     pub fn vendor(&self) -> PathBuf {
         self.inner.join("vendor")
     }
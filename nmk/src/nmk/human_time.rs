@@ -161,7 +161,7 @@ impl Iterator for Components {
                 Unit::Day => self.time.days().map(Component::days),
                 Unit::Hour => self.time.hours().map(Component::hours),
                 Unit::Minute => self.time.minutes().map(Component::minutes),
-                Unit::Second => Some(self.time.secs()).map(Component::seconds),
+                Unit::Second => Some(Component::seconds(self.time.secs())),
             };
             if component.is_some() {
                 break component;
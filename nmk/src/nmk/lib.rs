@@ -3,16 +3,24 @@
 mod _macros;
 pub mod arch;
 pub mod backup;
+pub mod clipboard;
 pub mod config;
 pub mod consts;
 pub mod container;
+pub mod datetime;
 pub mod dotfiles;
 pub mod error;
 pub mod gcs;
+pub mod history;
 pub mod home;
 pub mod human_time;
 pub mod platform;
+pub mod probe_cache;
 pub mod setup;
+pub mod shell_env;
+pub mod terminfo;
+pub mod timing;
+pub mod tmp_dir;
 pub mod tmux;
 pub mod vendor;
 
@@ -13,6 +13,13 @@ pub fn is_mac() -> bool {
     *PLATFORM == PlatformType::MacOs
 }
 
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    nix::unistd::gethostname(&mut buf)
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| String::from("unknown"))
+}
+
 impl PlatformType {
     pub fn detect() -> PlatformType {
         *PLATFORM
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::debug;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+
+use crate::setup::write_atomic;
+
+/// Bumped when meaning of cached values changes, older files are ignored
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    key: String,
+    value: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct CacheFile {
+    version: u32,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// Results of environment probes kept between runs, each valid while its key is unchanged
+///
+/// Key is built from what the probe depends on, e.g. [`file_key`] of a binary or `PATH`
+/// for [`which`], so a changed input misses the cache instead of returning stale value.
+#[derive(Debug)]
+pub struct ProbeCache {
+    /// `None` keeps cache in memory only, e.g. when temp dir isn't usable
+    path: Option<PathBuf>,
+    file: CacheFile,
+    dirty: bool,
+}
+
+impl ProbeCache {
+    /// Read cache at `path`, missing or unreadable file gives empty cache
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file = path
+            .as_deref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|contents| serde_json::from_slice::<CacheFile>(&contents).ok())
+            .filter(|file| file.version == FORMAT_VERSION)
+            .unwrap_or(CacheFile {
+                version: FORMAT_VERSION,
+                entries: BTreeMap::new(),
+            });
+        ProbeCache {
+            path,
+            file,
+            dirty: false,
+        }
+    }
+
+    /// Cached value of probe `name` if it was computed with the same `key`
+    pub fn get(&self, name: &str, key: &str) -> Option<&str> {
+        self.file
+            .entries
+            .get(name)
+            .filter(|entry| entry.key == key)
+            .map(|entry| entry.value.as_str())
+    }
+
+    pub fn insert(&mut self, name: &str, key: String, value: String) {
+        self.file
+            .entries
+            .insert(name.to_string(), Entry { key, value });
+        self.dirty = true;
+    }
+
+    /// Cached value of probe `name` or run `probe` and cache its result, failures aren't cached
+    pub fn get_or_probe<F>(&mut self, name: &str, key: String, probe: F) -> Option<String>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        if let Some(value) = self.get(name, &key) {
+            debug!("Using cached {} probe", name);
+            return Some(value.to_string());
+        }
+        let value = probe()?;
+        self.insert(name, key, value.clone());
+        Some(value)
+    }
+
+    /// Write cache back if a probe was run
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) if self.dirty => path,
+            _ => return Ok(()),
+        };
+        let contents = serde_json::to_vec(&self.file)?;
+        write_atomic(path, &contents, 0o600)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+static SHARED_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+static SHARED: Lazy<Mutex<ProbeCache>> =
+    Lazy::new(|| Mutex::new(ProbeCache::load(SHARED_PATH.get().cloned())));
+
+/// Keep cache shared by probes of this process in file at `path`
+///
+/// It has to be called before first probe, otherwise the cache stays in memory only.
+pub fn set_shared_path(path: PathBuf) {
+    let _ = SHARED_PATH.set(path);
+}
+
+/// Run `f` with cache shared by probes of this process, new entries are saved right away
+///
+/// `f` must not probe through the shared cache again.
+pub fn with_shared<T, F: FnOnce(&mut ProbeCache) -> T>(f: F) -> T {
+    let mut cache = SHARED.lock().unwrap();
+    let result = f(&mut cache);
+    if let Err(e) = cache.save() {
+        debug!("Failed to save probe cache: {}", e);
+    }
+    result
+}
+
+/// Find `bin` like [`which::which`], result is cached until `PATH` changes
+///
+/// Missing binary is cached as well, installing it changes mtime of its directory.
+pub fn which(bin: &str) -> Option<PathBuf> {
+    let name = format!("which {}", bin);
+    let key = path_key(&env::var_os("PATH").unwrap_or_default());
+    let value = with_shared(|cache| {
+        cache.get_or_probe(&name, key, || {
+            let found = which::which(bin).ok();
+            Some(found.map_or_else(String::new, |p| p.to_string_lossy().into_owned()))
+        })
+    });
+    value
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        // removed without touching directory, e.g. by bind mount
+        .filter(|path| path.exists())
+}
+
+/// Directories of `path` with their modification time
+fn path_key(path: &OsStr) -> String {
+    env::split_paths(path)
+        .map(|dir| {
+            let mtime = fs::metadata(&dir)
+                .map(|m| format!("{}.{}", m.mtime(), m.mtime_nsec()))
+                .unwrap_or_default();
+            format!("{}:{}", dir.display(), mtime)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Identity of file content, changes when file is replaced or modified
+pub fn file_key(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(format!(
+        "{}:{}.{}:{}:{}",
+        path.display(),
+        metadata.mtime(),
+        metadata.mtime_nsec(),
+        metadata.ino(),
+        metadata.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("probe-cache.json");
+        let bin = dir.join("bin");
+        fs::write(&bin, "v1").unwrap();
+        let key = file_key(&bin).unwrap();
+
+        let mut cache = ProbeCache::load(Some(path.clone()));
+        let value = cache.get_or_probe("version", key.clone(), || Some("1".to_string()));
+        assert_eq!(value.as_deref(), Some("1"));
+        assert_eq!(cache.get_or_probe("failed", key.clone(), || None), None);
+        cache.save().unwrap();
+
+        let mut cache = ProbeCache::load(Some(path.clone()));
+        let value = cache.get_or_probe("version", key, || unreachable!());
+        assert_eq!(value.as_deref(), Some("1"));
+        assert!(!cache.dirty);
+
+        fs::write(&bin, "version 2").unwrap();
+        let key = file_key(&bin).unwrap();
+        assert_eq!(cache.get("version", &key), None);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(ProbeCache::load(Some(path)).file.entries.is_empty());
+
+        let mut cache = ProbeCache::load(None);
+        cache.insert("version", key, "2".to_string());
+        cache.save().unwrap();
+    }
+
+    #[test]
+    fn test_path_key() {
+        use nix::sys::stat::utimes;
+        use nix::sys::time::{TimeVal, TimeValLike};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let old = TimeVal::seconds(1_600_000_000);
+        utimes(dir, &old, &old).unwrap();
+        let path = env::join_paths([dir, Path::new("/nonexistent")]).unwrap();
+        let key = path_key(&path);
+        assert_eq!(key.lines().count(), 2);
+        assert_eq!(path_key(&path), key);
+        fs::write(dir.join("xclip"), "").unwrap();
+        assert_ne!(path_key(&path), key);
+    }
+}
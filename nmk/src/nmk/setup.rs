@@ -20,6 +20,23 @@ pub fn install_busy(reader: &mut impl Read, dst: &Path) -> io::Result<()> {
     fs::rename(&tmp_dst, dst)
 }
 
+/// Write to a temporary file next to `dst` then `fs::rename`, readers never see partial content
+pub fn write_atomic(dst: &Path, contents: &[u8], mode: u32) -> io::Result<()> {
+    let mut tmp_dst = dst.to_path_buf().into_os_string();
+    tmp_dst.push(format!(".{}.tmp", std::process::id()));
+    let result = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .mode(mode)
+        .open(&tmp_dst)
+        .and_then(|mut f| f.write_all(contents).and_then(|_| f.sync_all()))
+        .and_then(|_| fs::rename(&tmp_dst, dst));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_dst);
+    }
+    result
+}
+
 fn open_for_install<P: AsRef<Path>>(dst: P) -> io::Result<File> {
     OpenOptions::new()
         .create(true)
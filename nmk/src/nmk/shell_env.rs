@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+
+/// Syntax of exported environment
+#[derive(Clone, Copy, Debug, Eq, PartialEq, strum::EnumString, strum::EnumVariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    Zsh,
+    Bash,
+    Fish,
+    Json,
+    /// `KEY=value` lines for `EnvironmentFile=` or `environment.d`
+    Systemd,
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn systemd_quote(value: &str) -> String {
+    let plain = value
+        .chars()
+        .all(|c| !c.is_whitespace() && !"\"'\\$".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Write `vars` so that they can be evaluated or loaded by `format`
+pub fn write(w: &mut dyn Write, format: Format, vars: &[(String, String)]) -> io::Result<()> {
+    if format == Format::Json {
+        let object: serde_json::Map<String, serde_json::Value> = vars
+            .iter()
+            .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+            .collect();
+        serde_json::to_writer_pretty(&mut *w, &object)?;
+        return writeln!(w);
+    }
+    for (key, value) in vars {
+        match format {
+            Format::Zsh | Format::Bash => {
+                writeln!(w, "export {}={}", key, shell_words::quote(value))?
+            }
+            Format::Fish => writeln!(w, "set -gx {} {}", key, fish_quote(value))?,
+            Format::Systemd => writeln!(w, "{}={}", key, systemd_quote(value))?,
+            Format::Json => unreachable!(),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: Format) -> String {
+        let vars = [
+            ("PATH", "/a/bin:/usr/bin"),
+            ("VIMINIT", "source '/home/my user/init.vim'"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+        let mut buf = Vec::new();
+        write(&mut buf, format, &vars).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_write() {
+        assert_eq!(
+            render(Format::Bash),
+            "export PATH=/a/bin:/usr/bin\n\
+            export VIMINIT='source '\\''/home/my user/init.vim'\\'''\n"
+        );
+        assert_eq!(
+            render(Format::Fish),
+            "set -gx PATH '/a/bin:/usr/bin'\n\
+            set -gx VIMINIT 'source \\'/home/my user/init.vim\\''\n"
+        );
+        assert_eq!(
+            render(Format::Systemd),
+            "PATH=/a/bin:/usr/bin\n\
+            VIMINIT=\"source '/home/my user/init.vim'\"\n"
+        );
+        let value: serde_json::Value = serde_json::from_str(&render(Format::Json)).unwrap();
+        assert_eq!(value["PATH"], "/a/bin:/usr/bin");
+    }
+}
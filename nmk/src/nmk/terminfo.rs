@@ -0,0 +1,361 @@
+//! Minimal reader of compiled terminfo entries, see term(5)
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::setup::write_atomic;
+
+const MAGIC_16BIT: u16 = 0o432;
+const MAGIC_32BIT: u16 = 0o1036;
+/// Index of `colors` in numeric capabilities
+const COLORS: usize = 13;
+/// Extended capabilities that tell terminal supports 24-bit colour
+const TRUE_COLOR_CAPABILITIES: &[&str] = &["RGB", "Tc"];
+
+/// Sources of entries that nmk installs for hosts that lack them
+pub const BUNDLED: &[(&str, &str)] =
+    &[("tmux-256color", include_str!("terminfo/tmux-256color.src"))];
+
+/// Directories searched for terminfo entries, in the same order as ncurses
+pub fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO").filter(|v| !v.is_empty()) {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".terminfo"));
+    }
+    if let Some(terminfo_dirs) = env::var_os("TERMINFO_DIRS") {
+        // empty entry means system default directory
+        dirs.extend(env::split_paths(&terminfo_dirs).filter(|p| !p.as_os_str().is_empty()));
+    }
+    for dir in &[
+        "/etc/terminfo",
+        "/lib/terminfo",
+        "/usr/share/terminfo",
+        "/usr/lib/terminfo",
+    ] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+/// Find compiled entry of terminal `name`
+///
+/// Entries are stored under a directory named by their first character, or its
+/// hexadecimal code on case-insensitive file systems such as macOS.
+pub fn find(name: &str) -> Option<PathBuf> {
+    let first = name.chars().next()?;
+    if name.contains('/') {
+        return None;
+    }
+    search_dirs().into_iter().find_map(|dir| {
+        let candidates = [
+            dir.join(first.to_string()).join(name),
+            dir.join(format!("{:x}", u32::from(first))).join(name),
+        ];
+        IntoIterator::into_iter(candidates).find(|p| p.is_file())
+    })
+}
+
+/// Compile bundled entries into `dir` with `tic`, return names of installed entries
+pub fn install_bundled(dir: &Path) -> crate::Result<Vec<&'static str>> {
+    fs::create_dir_all(dir)?;
+    let mut installed = Vec::with_capacity(BUNDLED.len());
+    for (name, source) in BUNDLED {
+        let source_path = dir.join(format!("{}.src", name));
+        write_atomic(&source_path, source.as_bytes(), 0o644)?;
+        let output = Command::new("tic")
+            .arg("-x")
+            .arg("-o")
+            .arg(dir)
+            .arg(&source_path)
+            .stdin(Stdio::null())
+            .output();
+        fs::remove_file(&source_path)?;
+        let output = output?;
+        if !output.status.success() {
+            let message = format!(
+                "tic failed to compile {}: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Err(io::Error::other(message).into());
+        }
+        installed.push(*name);
+    }
+    Ok(installed)
+}
+
+/// Value of `TERMINFO_DIRS` that searches `dir` before system directories
+pub fn prepend_terminfo_dirs(dir: &Path, current: Option<&str>) -> String {
+    let dir = dir.to_string_lossy();
+    match current.filter(|v| !v.is_empty()) {
+        Some(current) if current.split(':').any(|d| d == dir) => current.to_string(),
+        Some(current) => format!("{}:{}", dir, current),
+        // trailing empty entry means system default directories
+        None => format!("{}:", dir),
+    }
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Terminfo {
+    pub names: Vec<String>,
+    /// `colors` capability, `None` if absent
+    pub colors: Option<i32>,
+    /// Names of extended boolean and numeric capabilities that are present
+    pub extended: Vec<String>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(slice)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn count(&mut self) -> Option<usize> {
+        // -1 means the section is empty
+        let v = self.u16()? as i16;
+        Some(v.max(0) as usize)
+    }
+
+    fn number(&mut self, is_32bit: bool) -> Option<i32> {
+        if is_32bit {
+            self.take(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        } else {
+            self.u16().map(|v| i32::from(v as i16))
+        }
+    }
+
+    fn align(&mut self) {
+        self.pos += self.pos % 2;
+    }
+}
+
+fn c_str(table: &[u8], offset: usize) -> Option<&[u8]> {
+    let rest = table.get(offset..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    Some(&rest[..end])
+}
+
+impl Terminfo {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let mut r = Reader { bytes, pos: 0 };
+        let is_32bit = match r.u16()? {
+            MAGIC_16BIT => false,
+            MAGIC_32BIT => true,
+            _ => return None,
+        };
+        let names_size = r.count()?;
+        let bool_count = r.count()?;
+        let num_count = r.count()?;
+        let str_count = r.count()?;
+        let str_table_size = r.count()?;
+        let names = c_str(r.take(names_size)?, 0)?;
+        let names = String::from_utf8_lossy(names)
+            .split('|')
+            .map(String::from)
+            .collect();
+        r.take(bool_count)?;
+        r.align();
+        let mut numbers = Vec::with_capacity(num_count);
+        for _ in 0..num_count {
+            numbers.push(r.number(is_32bit)?);
+        }
+        let colors = numbers.get(COLORS).copied().filter(|&n| n >= 0);
+        r.take(str_count * 2 + str_table_size)?;
+        let mut terminfo = Terminfo {
+            names,
+            colors,
+            extended: Vec::new(),
+        };
+        r.align();
+        if r.pos < bytes.len() {
+            terminfo.extended = Self::parse_extended(&mut r, is_32bit).unwrap_or_default();
+        }
+        Some(terminfo)
+    }
+
+    fn parse_extended(r: &mut Reader, is_32bit: bool) -> Option<Vec<String>> {
+        let bool_count = r.count()?;
+        let num_count = r.count()?;
+        let str_count = r.count()?;
+        let _items = r.count()?;
+        let table_size = r.count()?;
+        let bools = r.take(bool_count)?;
+        r.align();
+        let mut numbers = Vec::with_capacity(num_count);
+        for _ in 0..num_count {
+            numbers.push(r.number(is_32bit)?);
+        }
+        let mut offsets = Vec::with_capacity(str_count);
+        for _ in 0..str_count {
+            offsets.push(r.u16()? as i16);
+        }
+        let name_count = bool_count + num_count + str_count;
+        let mut name_offsets = Vec::with_capacity(name_count);
+        for _ in 0..name_count {
+            name_offsets.push(r.u16()? as usize);
+        }
+        let table = r.take(table_size)?;
+        // names are stored after the last string value
+        let mut names_base = 0;
+        for &offset in offsets.iter().filter(|&&o| o >= 0) {
+            let value = c_str(table, offset as usize)?;
+            names_base = names_base.max(offset as usize + value.len() + 1);
+        }
+        let names = table.get(names_base..)?;
+        let name = |i: usize| -> Option<String> {
+            Some(String::from_utf8_lossy(c_str(names, name_offsets[i])?).into_owned())
+        };
+        let mut present = Vec::new();
+        for (i, &value) in bools.iter().enumerate() {
+            if value == 1 {
+                present.push(name(i)?);
+            }
+        }
+        for (i, &value) in numbers.iter().enumerate() {
+            if value >= 0 {
+                present.push(name(bool_count + i)?);
+            }
+        }
+        Some(present)
+    }
+
+    pub fn load(name: &str) -> Option<Self> {
+        Self::parse(&fs::read(find(name)?).ok()?)
+    }
+
+    pub fn has_true_color(&self) -> bool {
+        self.colors.is_some_and(|n| n >= 1 << 24)
+            || self
+                .extended
+                .iter()
+                .any(|c| TRUE_COLOR_CAPABILITIES.contains(&c.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u16(buf: &mut Vec<u8>, v: u16) {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// Build compiled entry with 32-bit numbers and extended booleans
+    fn compile(names: &str, colors: i32, ext_bools: &[(&str, bool)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let names = format!("{}\0", names);
+        for v in &[MAGIC_32BIT, names.len() as u16, 1, 14, 0, 0] {
+            push_u16(&mut buf, *v);
+        }
+        buf.extend_from_slice(names.as_bytes());
+        buf.push(1);
+        if buf.len() % 2 == 1 {
+            buf.push(0);
+        }
+        for i in 0..14 {
+            let n = if i == COLORS { colors } else { -1 };
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+        let mut table = Vec::new();
+        let mut offsets = Vec::new();
+        for (name, _) in ext_bools {
+            offsets.push(table.len() as u16);
+            table.extend_from_slice(name.as_bytes());
+            table.push(0);
+        }
+        let count = ext_bools.len() as u16;
+        for v in &[count, 0, 0, count, table.len() as u16] {
+            push_u16(&mut buf, *v);
+        }
+        buf.extend(ext_bools.iter().map(|(_, v)| u8::from(*v)));
+        if buf.len() % 2 == 1 {
+            buf.push(0);
+        }
+        for offset in offsets {
+            push_u16(&mut buf, offset);
+        }
+        buf.extend_from_slice(&table);
+        buf
+    }
+
+    #[test]
+    fn test_parse() {
+        let bytes = compile("xterm-direct|xterm with direct-color", 1 << 24, &[]);
+        let terminfo = Terminfo::parse(&bytes).unwrap();
+        assert_eq!(terminfo.names[0], "xterm-direct");
+        assert_eq!(terminfo.colors, Some(1 << 24));
+        assert!(terminfo.has_true_color());
+
+        let bytes = compile(
+            "alacritty",
+            256,
+            &[("AX", true), ("Tc", true), ("XT", false)],
+        );
+        let terminfo = Terminfo::parse(&bytes).unwrap();
+        assert_eq!(terminfo.colors, Some(256));
+        assert_eq!(terminfo.extended, vec!["AX", "Tc"]);
+        assert!(terminfo.has_true_color());
+
+        let bytes = compile("screen-256color", 256, &[("XT", false)]);
+        assert!(!Terminfo::parse(&bytes).unwrap().has_true_color());
+        assert_eq!(Terminfo::parse(b"not terminfo"), None);
+    }
+
+    #[test]
+    fn test_prepend_terminfo_dirs() {
+        let dir = Path::new("/nmk/terminfo");
+        assert_eq!(prepend_terminfo_dirs(dir, None), "/nmk/terminfo:");
+        assert_eq!(
+            prepend_terminfo_dirs(dir, Some("/opt/terminfo:")),
+            "/nmk/terminfo:/opt/terminfo:"
+        );
+        assert_eq!(
+            prepend_terminfo_dirs(dir, Some("/nmk/terminfo:")),
+            "/nmk/terminfo:"
+        );
+    }
+
+    #[test]
+    fn test_install_bundled() {
+        if which::which("tic").is_err() {
+            eprintln!("skipped test_install_bundled: tic not found");
+            return;
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        assert_eq!(install_bundled(dir).unwrap(), vec!["tmux-256color"]);
+        let entry = dir.join("t").join("tmux-256color");
+        let entry = if entry.exists() {
+            entry
+        } else {
+            dir.join("74").join("tmux-256color")
+        };
+        let terminfo = Terminfo::parse(&fs::read(entry).unwrap()).unwrap();
+        assert_eq!(terminfo.colors, Some(256));
+    }
+
+    #[test]
+    fn test_load_system_entry() {
+        // not every system has ncurses terminfo database
+        if let Some(terminfo) = Terminfo::load("xterm-256color") {
+            assert_eq!(terminfo.colors, Some(256));
+            assert!(terminfo.names.contains(&"xterm-256color".to_string()));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// Output format of startup profile
+#[derive(Clone, Copy, Debug, Eq, PartialEq, strum::EnumString, strum::EnumVariantNames)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// Duration of a named step of startup, phases measured inside another one are deeper
+#[derive(Clone, Debug, Serialize)]
+pub struct Phase {
+    pub name: &'static str,
+    pub depth: usize,
+    #[serde(rename = "micros", serialize_with = "as_micros")]
+    pub duration: Duration,
+}
+
+fn as_micros<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(d.as_micros() as u64)
+}
+
+#[derive(Default)]
+struct Recorder {
+    phases: Vec<Phase>,
+    depth: usize,
+}
+
+static RECORDER: Lazy<Mutex<Recorder>> = Lazy::new(Default::default);
+
+/// Run `f` and record how long it takes as phase `name`
+pub fn measure<T, F: FnOnce() -> T>(name: &'static str, f: F) -> T {
+    let index = {
+        let mut recorder = RECORDER.lock().unwrap();
+        let depth = recorder.depth;
+        recorder.depth += 1;
+        // pushed before running so phase comes before phases measured inside it
+        recorder.phases.push(Phase {
+            name,
+            depth,
+            duration: Duration::ZERO,
+        });
+        recorder.phases.len() - 1
+    };
+    let start = Instant::now();
+    let result = f();
+    let duration = start.elapsed();
+    let mut recorder = RECORDER.lock().unwrap();
+    recorder.depth -= 1;
+    recorder.phases[index].duration = duration;
+    result
+}
+
+/// Phases recorded so far in order of start
+pub fn phases() -> Vec<Phase> {
+    RECORDER.lock().unwrap().phases.clone()
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    phases: &'a [Phase],
+    #[serde(serialize_with = "as_micros")]
+    total_micros: Duration,
+}
+
+/// Print phases as indented table with share of `total`
+pub fn write_text(w: &mut dyn Write, phases: &[Phase], total: Duration) -> io::Result<()> {
+    let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+    for phase in phases {
+        let name = format!("{}{}", "  ".repeat(phase.depth), phase.name);
+        let percent = if total.is_zero() {
+            0.0
+        } else {
+            phase.duration.as_secs_f64() / total.as_secs_f64() * 100.0
+        };
+        writeln!(
+            w,
+            "{:<24} {:>8.2} ms {:>5.1}%",
+            name,
+            millis(phase.duration),
+            percent
+        )?;
+    }
+    writeln!(w, "{:<24} {:>8.2} ms", "total", millis(total))
+}
+
+pub fn write_json(w: &mut dyn Write, phases: &[Phase], total: Duration) -> io::Result<()> {
+    let report = Report {
+        phases,
+        total_micros: total,
+    };
+    serde_json::to_writer_pretty(&mut *w, &report)?;
+    writeln!(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let phases = [
+            Phase {
+                name: "render",
+                depth: 0,
+                duration: Duration::from_micros(1500),
+            },
+            Phase {
+                name: "context",
+                depth: 1,
+                duration: Duration::from_micros(500),
+            },
+        ];
+        let total = Duration::from_millis(3);
+        let mut buf = Vec::new();
+        write_text(&mut buf, &phases, total).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "render                       1.50 ms  50.0%\n\
+            \x20 context                    0.50 ms  16.7%\n\
+            total                        3.00 ms\n"
+        );
+        let mut buf = Vec::new();
+        write_json(&mut buf, &phases[1..], total).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value["phases"][0]["name"], "context");
+        assert_eq!(value["phases"][0]["micros"], 500);
+        assert_eq!(value["total_micros"], 3000);
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs::{self, DirBuilder, File};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use log::debug;
+use nix::unistd::Uid;
+
+/// Create directory only accessible by current user or check that existing one is
+///
+/// Directory in shared `/tmp` may be created by another user beforehand, so symlinks and
+/// directories owned by someone else are refused. Group or other permission of our own
+/// directory are removed.
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+    match DirBuilder::new().mode(0o700).create(path) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    // symlink_metadata doesn't follow symlink, so a link to directory is refused too
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.file_type().is_dir() {
+        return Err(io::Error::other(format!(
+            "{} is not a directory",
+            path.display()
+        )));
+    }
+    if metadata.uid() != Uid::current().as_raw() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is owned by uid {}, not current user",
+                path.display(),
+                metadata.uid()
+            ),
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        debug!("Removing group and other permission of {}", path.display());
+        fs::set_permissions(path, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// Mark file as used now so [`remove_stale`] keeps it, `false` if it doesn't exist
+pub fn touch(path: &Path) -> io::Result<bool> {
+    match File::options().append(true).open(path) {
+        Ok(file) => {
+            file.set_modified(SystemTime::now())?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Remove files of `dir` ending with one of `suffixes` not modified within `max_age`,
+/// return number of removed files
+pub fn remove_stale(dir: &Path, suffixes: &[&str], max_age: Duration) -> io::Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !suffixes.iter().any(|s| name.ends_with(s)) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        let is_stale = metadata.is_file()
+            && metadata
+                .modified()
+                .ok()
+                .and_then(|t| now.duration_since(t).ok())
+                .is_some_and(|age| age > max_age);
+        if is_stale {
+            match fs::remove_file(entry.path()) {
+                Ok(()) => removed += 1,
+                // another nmk may be cleaning at the same time
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// 64-bit FNV-1a hash of `bytes`
+///
+/// Unlike `DefaultHasher`, result does not change between builds, so file named by it can
+/// be reused by another nmk version.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn test_create_private_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        let dir = base.join("private");
+        create_private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        let link = base.join("link");
+        symlink(&dir, &link).unwrap();
+        assert!(create_private_dir(&link).is_err());
+        let file = base.join("file");
+        fs::write(&file, "").unwrap();
+        assert!(create_private_dir(&file).is_err());
+    }
+
+    #[test]
+    fn test_remove_stale() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let old = dir.join("old.tmux.conf");
+        let fresh = dir.join("fresh.tmux.conf");
+        let other = dir.join("other.txt");
+        for path in [&old, &fresh, &other] {
+            fs::write(path, "").unwrap();
+            let file = File::options().append(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(3600))
+                .unwrap();
+        }
+        assert!(touch(&fresh).unwrap());
+        assert!(!touch(&dir.join("missing")).unwrap());
+        let removed = remove_stale(dir, &[".tmux.conf"], Duration::from_secs(60)).unwrap();
+        assert_eq!(removed, 1);
+        assert!(!old.exists());
+        assert!(fresh.exists());
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(content_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
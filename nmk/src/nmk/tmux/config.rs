@@ -1,92 +1,273 @@
 use std::io;
 use std::io::Write;
 use std::path::PathBuf;
-use std::process::{Command, Stdio};
 
 use indoc::indoc;
 
 use crate::config::on_off;
 use crate::consts::env::NMK_HOME;
-use crate::platform::is_mac;
 
+use super::keys::{KeyMap, KeyOverride, Scheme, PREFIX_TABLE, ROOT_TABLE};
+use super::nested::{prefix_label, OFF_TABLE};
+use super::theme::{ColorDepth, Theme};
 use super::version::Version;
 
 const COPY_MODE: &str = "copy-mode -u";
+const COPY_MODE_KEYS: &str = "Copy Mode";
+const COPY_MODE_VI_TABLE: &str = "copy-mode-vi";
 const COPY_MODE_BOTTOM_EXIT: &str = "copy-mode -eu";
+const DEFAULT_PREFIX: &str = "C-b";
 const CWD: &str = "#{pane_current_path}";
 const F12_TABLE: &str = "F12";
 const LAST_SESSION: &str = "switch-client -l";
 const NEXT_PANE: &str = r#"select-pane -t :.+ \; display-panes"#;
+const PREFIX_KEYS: &str = "Prefix Keys";
+/// User option that lists `table=key` of every binding in config
+pub const BINDINGS_OPTION: &str = "@nmk-bindings";
 const NO_ENTER_COPY_MODE: &str = r##"#{?pane_in_mode,1,}#{?alternate_on,1,}"##;
 
 pub fn render(w: &mut dyn Write, c: &Context, v: Version) -> io::Result<()> {
     writeln!(w, "# Tmux {} configuration", v)?;
-    section(w, c, "Tmux Options", render_options)?;
-    section(w, c, "Prefix Keys", |w, _| {
-        writeln!(w, "bind-key -r C-b send-prefix")
-    })?;
-    writeln!(w, "bind-key -r o {}", NEXT_PANE)?;
-    writeln!(w, "bind-key -r C-o rotate-window")?;
-    writeln!(w, "bind-key C-c command-prompt")?;
-    writeln!(w, "bind-key C-l {}", LAST_SESSION)?;
-    writeln!(w, "{}", r##"bind-key C-t display-message '#{pane_tty}'"##)?;
-    writeln!(w, "bind-key -r Space next-layout")?;
-    section(w, c, "Function Key Binding", |w, _| {
-        for n in 1..=12 {
-            writeln!(w, "bind-key -n S-F{n} send-keys F{n}", n = n)?;
-        }
-        writeln!(w, "bind-key -n F1 {}", NEXT_PANE)?;
-        writeln!(w, "bind-key -n F2 last-window")?;
-        writeln!(w, "bind-key -n F3 previous-window")?;
-        writeln!(w, "bind-key -n F4 next-window")?;
-        writeln!(w, "bind-key -n F5 resize-pane -Z")?;
-        writeln!(w, "bind-key -n F6 {}", choose_tree(v))?;
-        writeln!(w, "bind-key -n F8 switch-client -n")
-    })?;
-    section(w, c, "F12 Key Table", |w, _| {
-        writeln!(w, "bind-key F12 send-keys F12")?;
-        writeln!(w, "bind-key -n F12 switch-client -T {}", F12_TABLE)?;
-        for n in 1..=9 {
-            writeln!(
-                w,
-                "bind-key -T {} {n} select-window -t {n}",
-                F12_TABLE,
-                n = n
-            )?;
-        }
-        for n in 1..=11 {
-            writeln!(w, "bind-key -T {} F{n} send-keys F{n}", F12_TABLE, n = n)?;
-        }
-        writeln!(w, "bind-key -T {} F12 detach-client", F12_TABLE)?;
-        writeln!(w, "bind-key -T {} -r Space next-layout", F12_TABLE)
-    })?;
-    section(w, c, "Pane Current Path", pane_current_path)?;
-    section(w, c, "Copy Mode", |w, _| {
-        writeln!(w, "bind-key C-u {}", COPY_MODE)?;
-        copy_to_system_clipboard(w)?;
-        // PageUp and PageDown special behaviors
-        //  If the condition is match, PageUp should enter copy mode
-        //  see https://www.reddit.com/r/tmux/comments/3paqoi/tmux_21_has_been_released/
-        writeln!(
-            w,
-            r#"bind-key -T root PageUp if-shell -F "{}" "send-keys PageUp" "{}""#,
-            NO_ENTER_COPY_MODE, COPY_MODE_BOTTOM_EXIT
-        )?;
-        half_pageup_pagedown(w)
-    })?;
+    section(w, c, "Tmux Options", |w, c| render_options(w, c, v))?;
+    let keymap = keymap(c, v);
+    for group in keymap.groups() {
+        section(w, c, group, |w, c| {
+            match group.as_str() {
+                PREFIX_KEYS => render_prefix(w, c)?,
+                COPY_MODE_KEYS => render_clipboard(w, c)?,
+                _ => {}
+            }
+            keymap.render_group(w, group)
+        })?;
+    }
+    if !keymap.unbound().is_empty() {
+        section(w, c, "Unbound Keys", |w, _| keymap.render_unbound(w))?;
+    }
+    section(w, c, "Reload", |w, _| render_binding_list(w, &keymap))?;
     // Colors
-    section(w, c, "Colors", |w, c| {
-        let color_config = if c.support_256_color {
-            include_str!("256color.conf")
-        } else {
-            include_str!("8color.conf")
-        };
-        writeln!(w, "{}", color_config)
-    })?;
+    section(w, c, "Colors", |w, c| c.theme.render(w, c.color_depth))?;
+    if !c.status_left.is_empty() || !c.status_right.is_empty() {
+        section(w, c, "Status Segments", render_status_segments)?;
+    }
+    if let Some(ref cmd) = c.autosave_command {
+        section(w, c, "Session Autosave", |w, _| {
+            // status line is redrawn every status-interval, the command decides if it is time to save
+            writeln!(w, r##"set-option -ga status-right "#({})""##, cmd)
+        })?;
+    }
     w.flush()
 }
 
-fn render_options(w: &mut dyn Write, c: &Context) -> io::Result<()> {
+/// Key bindings of configuration, grouped by section they are rendered in
+pub fn keymap(c: &Context, v: Version) -> KeyMap {
+    let mut k = KeyMap::default();
+    k.bind_repeat(PREFIX_KEYS, PREFIX_TABLE, &c.prefix, "send-prefix");
+    k.bind_repeat(PREFIX_KEYS, PREFIX_TABLE, "o", NEXT_PANE);
+    k.bind_repeat(PREFIX_KEYS, PREFIX_TABLE, "C-o", "rotate-window");
+    k.bind(PREFIX_KEYS, PREFIX_TABLE, "C-c", "command-prompt");
+    k.bind(PREFIX_KEYS, PREFIX_TABLE, "C-l", LAST_SESSION);
+    k.bind(
+        PREFIX_KEYS,
+        PREFIX_TABLE,
+        "C-t",
+        "display-message '#{pane_tty}'",
+    );
+    k.bind_repeat(PREFIX_KEYS, PREFIX_TABLE, "Space", "next-layout");
+    if let Some(ref cmd) = c.reload_command {
+        k.bind(
+            PREFIX_KEYS,
+            PREFIX_TABLE,
+            "R",
+            format!(r#"run-shell "{}""#, cmd),
+        );
+    }
+    for scheme in &c.schemes {
+        match scheme {
+            Scheme::Nmk => nmk_scheme(&mut k, v),
+            Scheme::Screen => screen_scheme(&mut k, &c.prefix),
+            Scheme::Vim => vim_scheme(&mut k),
+        }
+    }
+    off_mode(&mut k, c);
+    pane_current_path(&mut k);
+    copy_mode(&mut k, c);
+    k.apply("Custom Bindings", &c.key_overrides);
+    k
+}
+
+fn nmk_scheme(k: &mut KeyMap, v: Version) {
+    const GROUP: &str = "Function Key Binding";
+    for n in 1..=12 {
+        k.bind(
+            GROUP,
+            ROOT_TABLE,
+            &format!("S-F{}", n),
+            format!("send-keys F{}", n),
+        );
+    }
+    k.bind(GROUP, ROOT_TABLE, "F1", NEXT_PANE);
+    k.bind(GROUP, ROOT_TABLE, "F2", "last-window");
+    k.bind(GROUP, ROOT_TABLE, "F3", "previous-window");
+    k.bind(GROUP, ROOT_TABLE, "F4", "next-window");
+    k.bind(GROUP, ROOT_TABLE, "F5", "resize-pane -Z");
+    k.bind(GROUP, ROOT_TABLE, "F6", choose_tree(v));
+    k.bind(GROUP, ROOT_TABLE, "F8", "switch-client -n");
+
+    const F12_GROUP: &str = "F12 Key Table";
+    k.bind(F12_GROUP, PREFIX_TABLE, "F12", "send-keys F12");
+    k.bind(
+        F12_GROUP,
+        ROOT_TABLE,
+        "F12",
+        format!("switch-client -T {}", F12_TABLE),
+    );
+    for n in 1..=9 {
+        let key = n.to_string();
+        k.bind(
+            F12_GROUP,
+            F12_TABLE,
+            &key,
+            format!("select-window -t {}", n),
+        );
+    }
+    for n in 1..=11 {
+        let key = format!("F{}", n);
+        k.bind(F12_GROUP, F12_TABLE, &key, format!("send-keys {}", key));
+    }
+    k.bind(F12_GROUP, F12_TABLE, "F12", "detach-client");
+    k.bind_repeat(F12_GROUP, F12_TABLE, "Space", "next-layout");
+}
+
+/// GNU screen habits, pressing prefix twice goes to last window
+fn screen_scheme(k: &mut KeyMap, prefix: &str) {
+    const GROUP: &str = "Screen Keys";
+    k.bind(GROUP, PREFIX_TABLE, prefix, "last-window");
+    if let Some(letter) = prefix.strip_prefix("C-") {
+        k.bind(GROUP, PREFIX_TABLE, letter, "send-prefix");
+    }
+    k.bind(
+        GROUP,
+        PREFIX_TABLE,
+        "A",
+        r##"command-prompt -I "#W" "rename-window -- '%%'""##,
+    );
+    k.bind(GROUP, PREFIX_TABLE, "k", "confirm-before kill-window");
+    k.bind(GROUP, PREFIX_TABLE, "C-d", "detach-client");
+}
+
+fn vim_scheme(k: &mut KeyMap) {
+    const GROUP: &str = "Vim Keys";
+    let directions = [
+        ("h", "H", "L"),
+        ("j", "J", "D"),
+        ("k", "K", "U"),
+        ("l", "L", "R"),
+    ];
+    for (select, resize, flag) in IntoIterator::into_iter(directions) {
+        k.bind(
+            GROUP,
+            PREFIX_TABLE,
+            select,
+            format!("select-pane -{}", flag),
+        );
+        k.bind_repeat(
+            GROUP,
+            PREFIX_TABLE,
+            resize,
+            format!("resize-pane -{} 5", flag),
+        );
+    }
+}
+
+/// Off mode unbinds prefix and every key of outer tmux so they reach nested tmux,
+/// only the toggle key is bound in off key table
+fn off_mode(k: &mut KeyMap, c: &Context) {
+    const GROUP: &str = "Off Mode";
+    k.bind(
+        GROUP,
+        ROOT_TABLE,
+        &c.off_toggle_key,
+        format!(
+            r#"set-option prefix None \; set-option key-table {} \; refresh-client -S"#,
+            OFF_TABLE
+        ),
+    );
+    k.bind(
+        GROUP,
+        OFF_TABLE,
+        &c.off_toggle_key,
+        r#"set-option -u prefix \; set-option -u key-table \; refresh-client -S"#,
+    );
+}
+
+fn pane_current_path(k: &mut KeyMap) {
+    const GROUP: &str = "Pane Current Path";
+    let key_binding = &[
+        ("%", "split-window -h "),
+        ("|", "split-window -h "),
+        ("_", "split-window"),
+        ("c", "new-window"),
+        (r#"'"'"#, "split-window"),
+    ];
+    for (key, binding) in key_binding {
+        k.bind(
+            GROUP,
+            PREFIX_TABLE,
+            key,
+            format!("{} -c '{}'", binding, CWD),
+        );
+    }
+    k.bind(
+        GROUP,
+        PREFIX_TABLE,
+        "C",
+        format!(r#"command-prompt "new-session -c '{}' -s '%%'""#, CWD),
+    );
+}
+
+fn copy_mode(k: &mut KeyMap, c: &Context) {
+    k.bind(COPY_MODE_KEYS, PREFIX_TABLE, "C-u", COPY_MODE);
+    if let Some(ref cmd) = c.clipboard_command {
+        k.bind(
+            COPY_MODE_KEYS,
+            COPY_MODE_VI_TABLE,
+            "y",
+            format!(
+                r#"send-keys -X copy-pipe-and-cancel "{} clipboard copy""#,
+                cmd
+            ),
+        );
+        k.bind(
+            COPY_MODE_KEYS,
+            PREFIX_TABLE,
+            "C-v",
+            format!(r#"run-shell "{} clipboard paste --tmux""#, cmd),
+        );
+    }
+    // PageUp and PageDown special behaviors
+    //  If the condition is match, PageUp should enter copy mode
+    //  see https://www.reddit.com/r/tmux/comments/3paqoi/tmux_21_has_been_released/
+    k.bind(
+        COPY_MODE_KEYS,
+        ROOT_TABLE,
+        "PageUp",
+        format!(
+            r#"if-shell -F "{}" "send-keys PageUp" "{}""#,
+            NO_ENTER_COPY_MODE, COPY_MODE_BOTTOM_EXIT
+        ),
+    );
+    let key_binding = &[("PageUp", "halfpage-up"), ("PageDown", "halfpage-down")];
+    for (key, binding) in key_binding {
+        k.bind(
+            COPY_MODE_KEYS,
+            COPY_MODE_VI_TABLE,
+            key,
+            format!("send-keys -X {}", binding),
+        );
+    }
+}
+
+fn render_options(w: &mut dyn Write, c: &Context, v: Version) -> io::Result<()> {
     let options = indoc! {r##"
         set-option -g base-index 0
         set-option -g display-time 1200
@@ -94,16 +275,28 @@ fn render_options(w: &mut dyn Write, c: &Context) -> io::Result<()> {
         set-option -g status-keys emacs
         set-option -g status-left-length 20
         set-option -g status-right-length 60
-        set-option -g status-right "#{?client_prefix,^B ,}'#[fg=colour51]#{=40:pane_title}#[default]' %H:%M %Z %a, %d"
         set-window-option -g mode-keys vi
     "##};
     write!(w, "{}", options)?;
+    let title_style = match c.theme.title {
+        Some(color) => format!("#[fg={}]", color.downgrade(c.color_depth)),
+        None => String::new(),
+    };
+    writeln!(
+        w,
+        r##"set-option -g status-right "{}'{}#{{=40:pane_title}}#[default]' %H:%M %Z %a, %d""##,
+        key_indicator(c),
+        title_style
+    )?;
     writeln!(
         w,
         r#"set-option -g default-shell "{}""#,
         c.default_shell.display()
     )?;
     writeln!(w, r#"set-option -g default-terminal "{}""#, c.default_term)?;
+    if let Some(ref term) = c.true_color_term {
+        render_true_color(w, term, v)?;
+    }
     writeln!(
         w,
         r#"set-option -g detach-on-destroy "{}""#,
@@ -116,6 +309,111 @@ fn render_options(w: &mut dyn Write, c: &Context) -> io::Result<()> {
     )
 }
 
+/// Record bindings in user option, so reload can unbind keys removed from config
+fn render_binding_list(w: &mut dyn Write, keymap: &KeyMap) -> io::Result<()> {
+    let list: Vec<String> = keymap
+        .iter()
+        .map(|b| format!("{}={}", b.table, b.key))
+        .collect();
+    writeln!(
+        w,
+        r#"set-option -g {} "{}""#,
+        BINDINGS_OPTION,
+        escape_double_quoted(&list.join(" "))
+    )
+}
+
+fn escape_double_quoted(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '"' | '$') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Commands that unbind keys of `list`, value of [`BINDINGS_OPTION`] set by previous config
+pub fn render_unbind_list(w: &mut dyn Write, list: &str) -> io::Result<()> {
+    for entry in list.split_whitespace() {
+        if let Some((table, key)) = entry.split_once('=') {
+            writeln!(w, "unbind-key -T {} {}", table, key)?;
+        }
+    }
+    Ok(())
+}
+
+fn render_prefix(w: &mut dyn Write, c: &Context) -> io::Result<()> {
+    // always set, reload may switch back from another prefix
+    writeln!(w, "set-option -g prefix {}", c.prefix)?;
+    if c.prefix != DEFAULT_PREFIX {
+        writeln!(w, "unbind-key {}", DEFAULT_PREFIX)?;
+    }
+    Ok(())
+}
+
+/// Status line text that tells prefix is pressed or off mode is active
+fn key_indicator(c: &Context) -> String {
+    format!(
+        "#{{?#{{==:#{{client_key_table}},{table}}},OFF ,}}#{{?client_prefix,{} ,}}",
+        prefix_label(&c.prefix),
+        table = OFF_TABLE
+    )
+}
+
+fn render_status_segments(w: &mut dyn Write, c: &Context) -> io::Result<()> {
+    if !c.status_left.is_empty() {
+        writeln!(w, "set-option -g status-left-length 60")?;
+        writeln!(
+            w,
+            r##"set-option -g status-left "[#S] {} ""##,
+            c.status_left.join(" ")
+        )?;
+    }
+    if !c.status_right.is_empty() {
+        writeln!(w, "set-option -g status-right-length 100")?;
+        writeln!(
+            w,
+            r##"set-option -g status-right "{}{} %H:%M %Z %a, %d""##,
+            key_indicator(c),
+            c.status_right.join(" ")
+        )?;
+    }
+    Ok(())
+}
+
+/// Tell tmux the outer terminal `term` supports 24-bit colour
+fn render_true_color(w: &mut dyn Write, term: &str, v: Version) -> io::Result<()> {
+    if v >= Version::V32 {
+        writeln!(w, r#"set-option -as terminal-features ",{}:RGB""#, term)
+    } else {
+        writeln!(w, r#"set-option -ga terminal-overrides ",{}:Tc""#, term)
+    }
+}
+
+/// Choose `default-terminal`, `has_terminfo` tells if terminfo entry is installed
+///
+/// `tmux-256color` supports italics and other features that `screen-256color` lacks,
+/// but only tmux 3.2 relies on it for features like `RGB` reported to applications.
+/// Hosts without the entry fall back to `screen*` that every ncurses ships.
+pub fn default_terminal<F>(v: Version, depth: ColorDepth, has_terminfo: F) -> &'static str
+where
+    F: Fn(&str) -> bool,
+{
+    let candidates: &[&str] = match (depth >= ColorDepth::Indexed256, v >= Version::V32) {
+        (true, true) => &["tmux-256color", "screen-256color"],
+        (true, false) => &["screen-256color"],
+        (false, true) => &["tmux"],
+        (false, false) => &[],
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|name| has_terminfo(name))
+        .unwrap_or("screen")
+}
+
 fn section<F>(w: &mut dyn Write, c: &Context, name: &str, f: F) -> io::Result<()>
 where
     F: FnOnce(&mut dyn Write, &Context) -> io::Result<()>,
@@ -137,22 +435,23 @@ fn write_end_section(c: &mut dyn Write, name: &str) -> io::Result<()> {
     writeln!(c, "# {:-^118}", label)
 }
 
-fn pane_current_path(w: &mut dyn Write, _: &Context) -> io::Result<()> {
-    let key_binding = &[
-        ("%", "split-window -h "),
-        ("|", "split-window -h "),
-        ("_", "split-window"),
-        ("c", "new-window"),
-        (r#"'"'"#, "split-window"),
-    ];
-    for (key, binding) in key_binding {
-        writeln!(w, "bind-key {} {} -c '{}'", key, binding, CWD)?;
-    }
-    writeln!(
-        w,
-        r#"bind-key C command-prompt "new-session -c '{}' -s '%%'""#,
-        CWD
-    )
+fn parse_section_label<'a>(line: &'a str, kind: &str) -> Option<&'a str> {
+    line.strip_prefix("# ")?
+        .trim_matches('-')
+        .strip_prefix(' ')?
+        .strip_prefix(kind)?
+        .strip_prefix(' ')?
+        .strip_suffix(' ')
+}
+
+/// Name of section started by `line` written by [`render`]
+pub fn parse_start_section(line: &str) -> Option<&str> {
+    parse_section_label(line, "Start")
+}
+
+/// Name of section ended by `line` written by [`render`]
+pub fn parse_end_section(line: &str) -> Option<&str> {
+    parse_section_label(line, "End")
 }
 
 fn choose_tree(v: Version) -> String {
@@ -164,53 +463,325 @@ fn choose_tree(v: Version) -> String {
     vec.join(" ")
 }
 
-fn is_system_clipboard_available() -> bool {
-    let mut cmd = Command::new("xclip");
-    cmd.arg("-o").stdout(Stdio::null()).stderr(Stdio::null());
-    cmd.output().map_or(false, |output| output.status.success())
-}
-
-fn copy_to_system_clipboard(w: &mut dyn Write) -> io::Result<()> {
-    fn write_bind_config(w: &mut dyn Write, cmd: &str) -> io::Result<()> {
-        writeln!(
-            w,
-            r#"bind-key -T copy-mode-vi y send-keys -X copy-pipe-and-cancel "{}""#,
-            cmd
-        )
+/// Backend is picked by `nmk clipboard` when copying, so config stays valid when
+/// `DISPLAY` or `WAYLAND_DISPLAY` changes after server start
+fn render_clipboard(w: &mut dyn Write, c: &Context) -> io::Result<()> {
+    if c.set_clipboard {
+        writeln!(w, "set-option -s set-clipboard on")?;
     }
-    if is_mac() {
-        write_bind_config(w, "pbcopy")?;
-    } else if is_system_clipboard_available() {
-        write_bind_config(w, "xclip -selection clipboard")?;
-    }
-    Ok(())
-}
-
-fn half_pageup_pagedown(w: &mut dyn Write) -> io::Result<()> {
-    let key_binding = &[("PageUp", "halfpage-up"), ("PageDown", "halfpage-down")];
-    key_binding.iter().try_for_each(|(key, binding)| {
-        writeln!(
-            w,
-            "bind-key -T copy-mode-vi {} send-keys -X {}",
-            key, binding
-        )
-    })
+    writeln!(w, r#"set-option -ga update-environment " WAYLAND_DISPLAY""#)
 }
 
 pub struct Context {
     pub detach_on_destroy: bool,
-    pub support_256_color: bool,
+    pub color_depth: ColorDepth,
+    pub theme: Theme,
+    /// `TERM` of outer terminal that supports 24-bit colour
+    pub true_color_term: Option<String>,
     pub default_shell: PathBuf,
     pub default_term: String,
+    /// Prefix key, nested tmux uses different one from outer tmux
+    pub prefix: String,
+    /// Key that toggles off mode
+    pub off_toggle_key: String,
+    /// Binding schemes rendered in order
+    pub schemes: Vec<Scheme>,
+    /// Bindings from nmk.toml applied after schemes
+    pub key_overrides: Vec<KeyOverride>,
+    /// Command bound to prefix `R` that reloads config into running server
+    pub reload_command: Option<String>,
+    /// Shell quoted nmk binary that provides `nmk clipboard`
+    pub clipboard_command: Option<String>,
+    /// Let tmux forward copied text to outer terminal with OSC 52
+    pub set_clipboard: bool,
+    /// Command run periodically from status line to save sessions
+    pub autosave_command: Option<String>,
+    /// tmux formats of segments shown in `status-left`, e.g. `#(nmk status-segment load)`
+    pub status_left: Vec<String>,
+    /// tmux formats of segments shown in `status-right`
+    pub status_right: Vec<String>,
 }
 
 impl Default for Context {
     fn default() -> Self {
         Context {
             detach_on_destroy: false,
-            support_256_color: false,
+            color_depth: ColorDepth::Basic,
+            theme: Theme::default(),
+            true_color_term: None,
             default_shell: PathBuf::from("/bin/zsh"),
             default_term: String::from("screen"),
+            prefix: String::from(DEFAULT_PREFIX),
+            off_toggle_key: String::from("M-F12"),
+            schemes: vec![Scheme::Nmk],
+            key_overrides: Vec::new(),
+            reload_command: None,
+            clipboard_command: None,
+            set_clipboard: false,
+            autosave_command: None,
+            status_left: Vec::new(),
+            status_right: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::process::Command;
+    use std::{env, fs};
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::consts::bin::TMUX;
+    use crate::tmux::theme::PLAIN_THEME;
+    use crate::tmux::validate;
+
+    const SNAPSHOT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/nmk/tmux/snapshots");
+
+    /// Default context with 8 colours, no clipboard command and detach-on-destroy off
+    fn basic_context() -> Context {
+        Context {
+            theme: Theme::builtin(PLAIN_THEME).unwrap(),
+            default_shell: PathBuf::from("/bin/sh"),
+            ..Context::default()
+        }
+    }
+
+    /// Contexts covering every field, rendered for each version
+    fn snapshot_contexts() -> Vec<(&'static str, Context)> {
+        let basic = basic_context();
+        let color = Context {
+            color_depth: ColorDepth::Indexed256,
+            theme: Theme::builtin("default").unwrap(),
+            default_shell: PathBuf::from("/bin/sh"),
+            default_term: String::from("screen-256color"),
+            clipboard_command: Some(String::from("/usr/bin/nmk")),
+            ..Context::default()
+        };
+        let custom = Context {
+            detach_on_destroy: true,
+            color_depth: ColorDepth::TrueColor,
+            theme: Theme::builtin("gruvbox").unwrap(),
+            true_color_term: Some(String::from("alacritty")),
+            default_shell: PathBuf::from("/bin/sh"),
+            default_term: String::from("tmux-256color"),
+            prefix: String::from("C-a"),
+            schemes: vec![Scheme::Nmk, Scheme::Screen, Scheme::Vim],
+            key_overrides: vec![
+                KeyOverride {
+                    table: String::from(PREFIX_TABLE),
+                    key: String::from("r"),
+                    command: Some(String::from("source-file ~/.tmux.conf")),
+                    repeat: false,
+                },
+                KeyOverride {
+                    table: String::from(PREFIX_TABLE),
+                    key: String::from("C-z"),
+                    command: None,
+                    repeat: false,
+                },
+            ],
+            reload_command: Some(String::from("/usr/bin/nmk -L nmk reload")),
+            clipboard_command: Some(String::from("/usr/bin/nmk")),
+            set_clipboard: true,
+            autosave_command: Some(String::from("/usr/bin/nmk -L nmk session save --auto")),
+            status_left: vec![String::from("#(/usr/bin/nmk status-segment hostname)")],
+            status_right: vec![String::from(
+                "#(/usr/bin/nmk status-segment git #{q:pane_current_path})",
+            )],
+            ..Context::default()
+        };
+        // each of these differs from basic in one axis
+        let screen = Context {
+            prefix: String::from("C-a"),
+            schemes: vec![Scheme::Screen],
+            ..basic_context()
+        };
+        let vim = Context {
+            schemes: vec![Scheme::Vim],
+            ..basic_context()
+        };
+        let nested = Context {
+            prefix: String::from("C-s"),
+            off_toggle_key: String::from("F12"),
+            ..basic_context()
+        };
+        let osc52 = Context {
+            set_clipboard: true,
+            ..basic_context()
+        };
+        vec![
+            ("basic", basic),
+            ("256color", color),
+            ("custom", custom),
+            ("screen", screen),
+            ("vim", vim),
+            ("nested", nested),
+            ("osc52", osc52),
+        ]
+    }
+
+    fn snapshot_path(name: &str, v: Version) -> PathBuf {
+        Path::new(SNAPSHOT_DIR).join(format!("{}-{}.conf", name, v))
+    }
+
+    /// Run with `NMK_UPDATE_SNAPSHOTS=1` to write snapshots after changing renderer
+    #[test]
+    fn test_snapshots() {
+        let update = env::var_os("NMK_UPDATE_SNAPSHOTS").is_some();
+        let mut mismatches = Vec::new();
+        for (name, context) in snapshot_contexts() {
+            for v in Version::iter() {
+                let mut buf = Vec::new();
+                render(&mut buf, &context, v).unwrap();
+                let path = snapshot_path(name, v);
+                if update {
+                    fs::create_dir_all(SNAPSHOT_DIR).unwrap();
+                    fs::write(&path, &buf).unwrap();
+                } else if fs::read(&path).ok().as_deref() != Some(buf.as_slice()) {
+                    mismatches.push(path.display().to_string());
+                }
+            }
+        }
+        assert!(
+            mismatches.is_empty(),
+            "rendered config differs from snapshots, run with NMK_UPDATE_SNAPSHOTS=1 if expected: {:#?}",
+            mismatches
+        );
+    }
+
+    /// Major, minor and patch letter of version such as `3.1c`
+    fn version_key(s: &str) -> Option<(u32, u32, String)> {
+        let s = s.trim_start_matches("next-");
+        let (major, rest) = s.split_once('.')?;
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let minor = rest[..digits].parse().ok()?;
+        Some((major.parse().ok()?, minor, rest[digits..].to_string()))
+    }
+
+    #[test]
+    fn test_version_key() {
+        assert!(version_key("3.1c") < version_key("3.2"));
+        assert!(version_key("3.2a") < version_key("3.10"));
+        assert!(version_key("2.9") < version_key("2.9a"));
+        assert_eq!(version_key("next-3.4"), Some((3, 4, String::new())));
+        assert_eq!(version_key("master"), None);
+    }
+
+    /// Snapshots of versions up to installed tmux must be accepted by it
+    #[test]
+    fn test_snapshots_with_tmux() {
+        let output = match Command::new(TMUX).arg("-V").output() {
+            Ok(output) if output.status.success() => output,
+            _ => {
+                eprintln!("skipped test_snapshots_with_tmux: {} not found", TMUX);
+                return;
+            }
+        };
+        // `tmux 3.3a` or `tmux next-3.4`, may be newer than any known version
+        let output = String::from_utf8_lossy(&output.stdout);
+        let installed = match output.split_whitespace().nth(1).and_then(version_key) {
+            Some(installed) => installed,
+            None => {
+                eprintln!(
+                    "skipped test_snapshots_with_tmux: unknown {}",
+                    output.trim()
+                );
+                return;
+            }
+        };
+        for (name, _) in snapshot_contexts() {
+            for v in Version::iter().filter(|v| version_key(v.as_str()).unwrap() <= installed) {
+                let config = fs::read_to_string(snapshot_path(name, v)).unwrap();
+                let problems = validate::validate(&config).unwrap();
+                assert!(
+                    problems.is_empty(),
+                    "{} rejected {}-{}.conf: {:#?}",
+                    output.trim(),
+                    name,
+                    v,
+                    problems
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_terminal() {
+        let all = |_: &str| true;
+        let none = |_: &str| false;
+        assert_eq!(
+            default_terminal(Version::V32, ColorDepth::TrueColor, all),
+            "tmux-256color"
+        );
+        assert_eq!(
+            default_terminal(Version::V32, ColorDepth::Indexed256, |t| t
+                != "tmux-256color"),
+            "screen-256color"
+        );
+        assert_eq!(
+            default_terminal(Version::V32, ColorDepth::Indexed256, none),
+            "screen"
+        );
+        assert_eq!(
+            default_terminal(Version::V31, ColorDepth::Indexed256, all),
+            "screen-256color"
+        );
+        assert_eq!(
+            default_terminal(Version::V32, ColorDepth::Basic, all),
+            "tmux"
+        );
+        assert_eq!(
+            default_terminal(Version::V26, ColorDepth::Basic, all),
+            "screen"
+        );
+    }
+
+    #[test]
+    fn test_binding_list() {
+        let mut keymap = KeyMap::default();
+        keymap.bind("A", PREFIX_TABLE, r#"'"'"#, "split-window");
+        keymap.bind("A", "copy-mode-vi", "y", "send-keys -X copy-selection");
+        let mut buf = Vec::new();
+        render_binding_list(&mut buf, &keymap).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "set-option -g @nmk-bindings \"prefix='\\\"' copy-mode-vi=y\"\n"
+        );
+        let mut buf = Vec::new();
+        render_unbind_list(&mut buf, "prefix='\"' copy-mode-vi=y").unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "unbind-key -T prefix '\"'\nunbind-key -T copy-mode-vi y\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_section() {
+        let mut buf = Vec::new();
+        write_start_section(&mut buf, "Copy Mode").unwrap();
+        write_end_section(&mut buf, "Copy Mode").unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(parse_start_section(lines[0]), Some("Copy Mode"));
+        assert_eq!(parse_end_section(lines[0]), None);
+        assert_eq!(parse_end_section(lines[1]), Some("Copy Mode"));
+        assert_eq!(parse_start_section("# Tmux 3.2a configuration"), None);
+    }
+
+    #[test]
+    fn test_render_true_color() {
+        let mut buf = Vec::new();
+        render_true_color(&mut buf, "alacritty", Version::V32a).unwrap();
+        render_true_color(&mut buf, "*", Version::V29).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "set-option -as terminal-features \",alacritty:RGB\"\n\
+            set-option -ga terminal-overrides \",*:Tc\"\n"
+        );
+    }
+}
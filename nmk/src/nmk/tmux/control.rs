@@ -0,0 +1,465 @@
+//! Client of tmux control mode, see CONTROL MODE in tmux manual
+//!
+//! tmux replies to each command with a block of output lines wrapped by `%begin` and
+//! `%end` or `%error`. Outside of blocks, it sends lines starting with `%` to notify
+//! changes e.g. pane output and window creation.
+use std::fmt::{self, Display};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::consts::bin::TMUX;
+
+#[derive(Debug)]
+pub enum ControlError {
+    Io(io::Error),
+    /// tmux replied with `%error` and these lines
+    Command(Vec<String>),
+    /// Control client is no longer connected
+    Exited,
+    /// Command line can't be sent because it contains a newline
+    InvalidCommand(String),
+}
+
+impl Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::Io(e) => write!(f, "{}", e),
+            ControlError::Command(lines) => write!(f, "{}", lines.join("\n")),
+            ControlError::Exited => write!(f, "tmux control client exited"),
+            ControlError::InvalidCommand(cmd) => write!(f, "invalid command: {:?}", cmd),
+        }
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+impl From<io::Error> for ControlError {
+    fn from(e: io::Error) -> Self {
+        ControlError::Io(e)
+    }
+}
+
+/// Output block of a command
+#[derive(Debug, Eq, PartialEq)]
+pub struct Response {
+    /// Command number assigned by tmux
+    pub number: u64,
+    pub lines: Vec<String>,
+    pub is_error: bool,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Notification {
+    /// Pane produced output, `data` is unescaped
+    Output {
+        pane: String,
+        data: Vec<u8>,
+    },
+    WindowAdd {
+        window: String,
+    },
+    WindowClose {
+        window: String,
+    },
+    WindowRenamed {
+        window: String,
+        name: String,
+    },
+    /// Session attached to the control client changed
+    SessionChanged {
+        session: String,
+        name: String,
+    },
+    /// A session was created or destroyed
+    SessionsChanged,
+    /// Control client is exiting
+    Exit {
+        reason: Option<String>,
+    },
+    /// Any other notification, the whole line
+    Other(String),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Message {
+    Response(Response),
+    Notification(Notification),
+}
+
+/// Decode octal escape `\ooo` used by `%output`
+fn unescape(s: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let octal = s
+            .get(i + 1..i + 4)
+            .filter(|d| d.iter().all(|b| (b'0'..=b'7').contains(b)));
+        match octal {
+            Some(digits) if s[i] == b'\\' => {
+                let value = digits
+                    .iter()
+                    .fold(0u32, |acc, &d| acc * 8 + u32::from(d - b'0'));
+                data.push(value as u8);
+                i += 4;
+            }
+            _ => {
+                data.push(s[i]);
+                i += 1;
+            }
+        }
+    }
+    data
+}
+
+fn parse_block_number(rest: &str) -> Option<u64> {
+    // `<time> <number> <flags>`
+    rest.split(' ').nth(1)?.parse().ok()
+}
+
+fn parse_notification(line: &[u8]) -> Notification {
+    if let Some(rest) = line.strip_prefix(b"%output ") {
+        let space = rest.iter().position(|&b| b == b' ').unwrap_or(rest.len());
+        let data = rest.get(space + 1..).unwrap_or_default();
+        return Notification::Output {
+            pane: String::from_utf8_lossy(&rest[..space]).into_owned(),
+            data: unescape(data),
+        };
+    }
+    let line = String::from_utf8_lossy(line);
+    let mut iter = line.splitn(3, ' ');
+    let name = iter.next().unwrap_or_default();
+    let first = iter.next().map(String::from);
+    let second = iter.next().map(String::from);
+    match (name, first, second) {
+        ("%window-add", Some(window), _) => Notification::WindowAdd { window },
+        ("%window-close", Some(window), _) => Notification::WindowClose { window },
+        ("%window-renamed", Some(window), Some(name)) => {
+            Notification::WindowRenamed { window, name }
+        }
+        ("%session-changed", Some(session), Some(name)) => {
+            Notification::SessionChanged { session, name }
+        }
+        ("%sessions-changed", None, None) => Notification::SessionsChanged,
+        ("%exit", first, second) => Notification::Exit {
+            reason: match (first, second) {
+                (Some(a), Some(b)) => Some(format!("{} {}", a, b)),
+                (a, _) => a,
+            },
+        },
+        _ => Notification::Other(line.into_owned()),
+    }
+}
+
+/// Turn lines of control mode output into messages
+///
+/// tmux never sends notification inside a block, so any line in a block is command output.
+#[derive(Debug, Default)]
+pub struct Parser {
+    block: Option<(u64, Vec<String>)>,
+}
+
+impl Parser {
+    /// Feed a line without trailing newline, return message once it is complete
+    pub fn feed(&mut self, line: &[u8]) -> Option<Message> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if let Some((number, mut lines)) = self.block.take() {
+            let text = String::from_utf8_lossy(line);
+            let is_end = text.strip_prefix("%end ").map(|rest| (rest, false));
+            let is_error = text.strip_prefix("%error ").map(|rest| (rest, true));
+            match is_end.or(is_error) {
+                Some((rest, is_error)) if parse_block_number(rest) == Some(number) => {
+                    return Some(Message::Response(Response {
+                        number,
+                        lines,
+                        is_error,
+                    }));
+                }
+                _ => {
+                    lines.push(text.into_owned());
+                    self.block = Some((number, lines));
+                    return None;
+                }
+            }
+        }
+        if let Some(rest) = line.strip_prefix(b"%begin ") {
+            let number = parse_block_number(&String::from_utf8_lossy(rest))?;
+            self.block = Some((number, Vec::new()));
+            None
+        } else if line.starts_with(b"%") {
+            Some(Message::Notification(parse_notification(line)))
+        } else {
+            None
+        }
+    }
+}
+
+fn read_messages(
+    stdout: impl BufRead,
+    responses: Sender<Response>,
+    notifications: Sender<Notification>,
+) {
+    let mut parser = Parser::default();
+    for line in stdout.split(b'\n') {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let is_connected = match parser.feed(&line) {
+            Some(Message::Response(r)) => responses.send(r).is_ok(),
+            // nobody listening to notifications is fine
+            Some(Message::Notification(n)) => {
+                let _ = notifications.send(n);
+                true
+            }
+            None => true,
+        };
+        if !is_connected {
+            break;
+        }
+    }
+}
+
+/// Long-lived connection to tmux server through `tmux -C`
+///
+/// Commands are answered in order, so [`ControlClient::command`] blocks until its
+/// own response arrives. Notifications are queued in [`ControlClient::events`].
+pub struct ControlClient {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    responses: Receiver<Response>,
+    events: Receiver<Notification>,
+}
+
+impl ControlClient {
+    /// Start control client on `socket` that runs `initial` command e.g. `new-session` or
+    /// `attach-session -t <name>`
+    pub fn spawn(
+        socket: &str,
+        config: Option<&Path>,
+        initial: &[&str],
+    ) -> Result<Self, ControlError> {
+        let mut cmd = Command::new(TMUX);
+        cmd.args(["-L", socket]);
+        if let Some(config) = config {
+            cmd.arg("-f").arg(config);
+        }
+        cmd.arg("-C")
+            .args(initial)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        let mut child = cmd.spawn()?;
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (response_sender, responses) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        thread::spawn(move || read_messages(BufReader::new(stdout), response_sender, event_sender));
+        let mut client = ControlClient {
+            child,
+            stdin,
+            responses,
+            events,
+        };
+        // tmux replies to the initial command like any other command
+        client.wait_response()?;
+        Ok(client)
+    }
+
+    /// Attach control client to existing session
+    pub fn attach(socket: &str, session: &str) -> Result<Self, ControlError> {
+        Self::spawn(socket, None, &["attach-session", "-t", session])
+    }
+
+    fn wait_response(&mut self) -> Result<Vec<String>, ControlError> {
+        let response = self.responses.recv().map_err(|_| ControlError::Exited)?;
+        if response.is_error {
+            Err(ControlError::Command(response.lines))
+        } else {
+            Ok(response.lines)
+        }
+    }
+
+    /// Run a command line, quoted the same way as in tmux config, and return its output
+    pub fn command(&mut self, command: &str) -> Result<Vec<String>, ControlError> {
+        if command.contains('\n') {
+            return Err(ControlError::InvalidCommand(command.to_string()));
+        }
+        let stdin = self.stdin.as_mut().ok_or(ControlError::Exited)?;
+        writeln!(stdin, "{}", command)?;
+        stdin.flush()?;
+        self.wait_response()
+    }
+
+    /// Notifications received so far and in the future
+    pub fn events(&self) -> &Receiver<Notification> {
+        &self.events
+    }
+
+    /// Wait for next notification matching `predicate`, skipping others
+    pub fn wait_for<P>(
+        &self,
+        timeout: Duration,
+        mut predicate: P,
+    ) -> Result<Notification, ControlError>
+    where
+        P: FnMut(&Notification) -> bool,
+    {
+        loop {
+            match self.events.recv_timeout(timeout) {
+                Ok(n) if predicate(&n) => return Ok(n),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(ControlError::Io(io::ErrorKind::TimedOut.into()))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(ControlError::Exited),
+            }
+        }
+    }
+
+    /// Detach by closing stdin and wait for tmux client to exit
+    pub fn close(mut self) -> Result<(), ControlError> {
+        self.stdin.take();
+        self.child.wait()?;
+        Ok(())
+    }
+}
+
+impl Drop for ControlClient {
+    fn drop(&mut self) {
+        if self.stdin.take().is_some() {
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(input: &[u8]) -> Vec<Message> {
+        let mut parser = Parser::default();
+        input
+            .split(|&b| b == b'\n')
+            .filter_map(|line| parser.feed(line))
+            .collect()
+    }
+
+    #[test]
+    fn test_parser() {
+        let input = b"%begin 1625063101 260 0\n%end 1625063101 260 0\n%window-add @0\n\
+            %session-changed $0 main\n%begin 1625063101 266 1\n%output inside block\n\
+            %end 1625063101 266 1\n%begin 1625063101 267 1\nparse error: unknown command: x\n\
+            %error 1625063101 267 1\n%output %1 a\\134tb\\015\\012\n%exit detached\n";
+        let messages = feed_all(input);
+        let expect = vec![
+            Message::Response(Response {
+                number: 260,
+                lines: vec![],
+                is_error: false,
+            }),
+            Message::Notification(Notification::WindowAdd {
+                window: "@0".to_string(),
+            }),
+            Message::Notification(Notification::SessionChanged {
+                session: "$0".to_string(),
+                name: "main".to_string(),
+            }),
+            Message::Response(Response {
+                number: 266,
+                lines: vec!["%output inside block".to_string()],
+                is_error: false,
+            }),
+            Message::Response(Response {
+                number: 267,
+                lines: vec!["parse error: unknown command: x".to_string()],
+                is_error: true,
+            }),
+            Message::Notification(Notification::Output {
+                pane: "%1".to_string(),
+                data: b"a\\tb\r\n".to_vec(),
+            }),
+            Message::Notification(Notification::Exit {
+                reason: Some("detached".to_string()),
+            }),
+        ];
+        assert_eq!(messages, expect);
+    }
+
+    /// Kill test server even if assertion fails while it is running
+    struct ServerGuard<'a>(&'a str);
+
+    impl Drop for ServerGuard<'_> {
+        fn drop(&mut self) {
+            let _ = Command::new(TMUX)
+                .args(["-L", self.0, "kill-server"])
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+
+    #[test]
+    fn test_control_client() {
+        if which::which(TMUX).is_err() {
+            eprintln!("skipped test_control_client: {} not found", TMUX);
+            return;
+        }
+        let socket = format!("nmk-control-test-{}", std::process::id());
+        let _guard = ServerGuard(&socket);
+        let mut client = ControlClient::spawn(
+            &socket,
+            Some(Path::new("/dev/null")),
+            &[
+                "new-session",
+                "-s",
+                "control",
+                "-x",
+                "80",
+                "-y",
+                "24",
+                "cat",
+            ],
+        )
+        .unwrap();
+        let timeout = Duration::from_secs(5);
+        let sessions = client
+            .command("list-sessions -F '#{session_name}'")
+            .unwrap();
+        assert_eq!(sessions, vec!["control"]);
+
+        client.command("new-window -n second cat").unwrap();
+        let added = client
+            .wait_for(timeout, |n| matches!(n, Notification::WindowAdd { .. }))
+            .unwrap();
+        assert!(matches!(added, Notification::WindowAdd { window } if window.starts_with('@')));
+
+        client.command("send-keys -l 'hello nmk'").unwrap();
+        client.command("send-keys Enter").unwrap();
+        let mut output = Vec::new();
+        client
+            .wait_for(timeout, |n| {
+                if let Notification::Output { data, .. } = n {
+                    output.extend_from_slice(data);
+                }
+                String::from_utf8_lossy(&output).contains("hello nmk\r\n")
+            })
+            .unwrap();
+
+        let err = client.command("no-such-command").unwrap_err();
+        assert!(matches!(err, ControlError::Command(_)));
+        assert!(matches!(
+            client.command("a\nb"),
+            Err(ControlError::InvalidCommand(_))
+        ));
+
+        let socket_path = client
+            .command("display-message -p '#{socket_path}'")
+            .unwrap();
+        client.command("kill-server").unwrap_or_default();
+        client.close().unwrap();
+        let _ = std::fs::remove_file(&socket_path[0]);
+    }
+}
@@ -0,0 +1,281 @@
+use std::io::{self, Write};
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+pub const PREFIX_TABLE: &str = "prefix";
+pub const ROOT_TABLE: &str = "root";
+
+/// Set of key bindings rendered into tmux configuration
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    PartialEq,
+    strum::AsStaticStr,
+    strum::EnumString,
+    strum::EnumVariantNames,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Scheme {
+    /// Function keys in root table and `F12` key table
+    Nmk,
+    /// `C-a` prefix, `C-a C-a` switches to last window and `C-a a` sends prefix
+    Screen,
+    /// `h`, `j`, `k`, `l` select pane, upper case resizes it with repeat
+    Vim,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        strum::AsStaticRef::as_static(self)
+    }
+}
+
+/// Binding from `[[keys.bind]]` of nmk.toml, applied after schemes
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct KeyOverride {
+    #[serde(default = "default_table")]
+    pub table: String,
+    pub key: String,
+    /// Command to bind, the key is unbound if not given
+    pub command: Option<String>,
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+fn default_table() -> String {
+    PREFIX_TABLE.to_string()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    /// Prefix key, default to `C-a` with screen scheme or `C-b` otherwise
+    pub prefix: Option<String>,
+    pub schemes: Vec<Scheme>,
+    pub bind: Vec<KeyOverride>,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        KeysConfig {
+            prefix: None,
+            schemes: vec![Scheme::Nmk],
+            bind: Vec::new(),
+        }
+    }
+}
+
+impl KeysConfig {
+    pub fn prefix(&self) -> &str {
+        match self.prefix {
+            Some(ref prefix) => prefix,
+            None if self.schemes.contains(&Scheme::Screen) => "C-a",
+            None => "C-b",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Binding {
+    /// Configuration section the binding is rendered in
+    pub group: String,
+    pub table: String,
+    pub key: String,
+    pub repeat: bool,
+    pub command: String,
+}
+
+impl Binding {
+    pub fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "bind-key")?;
+        if self.repeat {
+            write!(w, " -r")?;
+        }
+        match self.table.as_str() {
+            PREFIX_TABLE => {}
+            ROOT_TABLE => write!(w, " -n")?,
+            table => write!(w, " -T {}", table)?,
+        }
+        writeln!(w, " {} {}", self.key, self.command)
+    }
+}
+
+/// Effective key bindings, a later binding of the same key replaces earlier one
+#[derive(Debug, Default)]
+pub struct KeyMap {
+    groups: Vec<String>,
+    bindings: IndexMap<(String, String), Binding>,
+    /// Keys unbound by overrides, they may be tmux default bindings
+    unbound: Vec<(String, String)>,
+}
+
+impl KeyMap {
+    fn insert(&mut self, group: &str, table: &str, key: &str, repeat: bool, command: String) {
+        if !self.groups.iter().any(|g| g == group) {
+            self.groups.push(group.to_string());
+        }
+        let id = (table.to_string(), key.to_string());
+        self.bindings.shift_remove(&id);
+        self.unbound.retain(|u| *u != id);
+        let binding = Binding {
+            group: group.to_string(),
+            table: table.to_string(),
+            key: key.to_string(),
+            repeat,
+            command,
+        };
+        self.bindings.insert(id, binding);
+    }
+
+    pub fn bind<S: Into<String>>(&mut self, group: &str, table: &str, key: &str, command: S) {
+        self.insert(group, table, key, false, command.into());
+    }
+
+    pub fn bind_repeat<S: Into<String>>(
+        &mut self,
+        group: &str,
+        table: &str,
+        key: &str,
+        command: S,
+    ) {
+        self.insert(group, table, key, true, command.into());
+    }
+
+    pub fn unbind(&mut self, table: &str, key: &str) {
+        let id = (table.to_string(), key.to_string());
+        self.bindings.shift_remove(&id);
+        if !self.unbound.contains(&id) {
+            self.unbound.push(id);
+        }
+    }
+
+    pub fn apply(&mut self, group: &str, overrides: &[KeyOverride]) {
+        for o in overrides {
+            match o.command {
+                Some(ref command) => {
+                    self.insert(group, &o.table, &o.key, o.repeat, command.clone())
+                }
+                None => self.unbind(&o.table, &o.key),
+            }
+        }
+    }
+
+    /// Group names in order of first binding
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Binding> {
+        self.bindings.values()
+    }
+
+    pub fn unbound(&self) -> &[(String, String)] {
+        &self.unbound
+    }
+
+    /// Key tables in order of first binding
+    pub fn tables(&self) -> Vec<&str> {
+        let mut tables: Vec<&str> = Vec::new();
+        for b in self.iter() {
+            if !tables.contains(&b.table.as_str()) {
+                tables.push(&b.table);
+            }
+        }
+        tables
+    }
+
+    pub fn render_group(&self, w: &mut dyn Write, group: &str) -> io::Result<()> {
+        self.iter()
+            .filter(|b| b.group == group)
+            .try_for_each(|b| b.render(w))
+    }
+
+    pub fn render_unbound(&self, w: &mut dyn Write) -> io::Result<()> {
+        for (table, key) in &self.unbound {
+            match table.as_str() {
+                PREFIX_TABLE => writeln!(w, "unbind-key {}", key)?,
+                ROOT_TABLE => writeln!(w, "unbind-key -n {}", key)?,
+                table => writeln!(w, "unbind-key -T {} {}", table, key)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Bindings grouped by key table, used as cheat sheet, `only` limits to one table
+    pub fn cheat_sheet(&self, w: &mut dyn Write, only: Option<&str>) -> io::Result<()> {
+        let tables = self
+            .tables()
+            .into_iter()
+            .filter(|t| only.is_none_or(|only| only == *t));
+        for (index, table) in tables.enumerate() {
+            if index > 0 {
+                writeln!(w)?;
+            }
+            writeln!(w, "{}", table)?;
+            for b in self.iter().filter(|b| b.table == table) {
+                let repeat = if b.repeat { "(repeat)" } else { "" };
+                writeln!(w, "  {:<10} {:<8} {}", b.key, repeat, b.command)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keymap() {
+        let mut keymap = KeyMap::default();
+        keymap.bind("A", PREFIX_TABLE, "C-a", "send-prefix");
+        keymap.bind_repeat("A", "F12", "Space", "next-layout");
+        keymap.bind("B", ROOT_TABLE, "F1", "last-window");
+        keymap.bind("B", PREFIX_TABLE, "C-a", "last-window");
+        let overrides: Vec<KeyOverride> = toml::from_str::<KeysConfig>(
+            r#"
+            [[bind]]
+            key = "r"
+            command = "source-file ~/.tmux.conf"
+            [[bind]]
+            table = "root"
+            key = "F1"
+            "#,
+        )
+        .unwrap()
+        .bind;
+        keymap.apply("Custom", &overrides);
+        assert_eq!(keymap.groups(), ["A", "B", "Custom"]);
+        assert_eq!(keymap.tables(), ["F12", "prefix"]);
+
+        let mut buf = Vec::new();
+        for group in keymap.groups() {
+            keymap.render_group(&mut buf, group).unwrap();
+        }
+        keymap.render_unbound(&mut buf).unwrap();
+        keymap.cheat_sheet(&mut buf, Some("F12")).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "bind-key -r -T F12 Space next-layout\n\
+            bind-key C-a last-window\n\
+            bind-key r source-file ~/.tmux.conf\n\
+            unbind-key -n F1\n\
+            F12\n  Space      (repeat) next-layout\n"
+        );
+    }
+
+    #[test]
+    fn test_prefix() {
+        let config: KeysConfig = toml::from_str(r#"schemes = ["screen", "vim"]"#).unwrap();
+        assert_eq!(config.prefix(), "C-a");
+        assert_eq!(KeysConfig::default().prefix(), "C-b");
+        let config: KeysConfig = toml::from_str(r#"prefix = "C-s""#).unwrap();
+        assert_eq!(config.prefix(), "C-s");
+        assert_eq!(config.schemes, vec![Scheme::Nmk]);
+    }
+}
@@ -0,0 +1,310 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::version::Version;
+use crate::home::expand_tilde;
+
+/// Argument that separates tmux commands when they are given as process arguments
+pub const COMMAND_SEPARATOR: &str = ";";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// New pane is on the right, `split-window -h`
+    Horizontal,
+    /// New pane is below, `split-window -v`
+    #[default]
+    Vertical,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Pane {
+    /// Working directory, relative path is resolved against window directory
+    pub cwd: Option<PathBuf>,
+    /// Command typed into the shell once pane is created
+    pub command: Option<String>,
+    /// How this pane is split from previous pane, ignored for the first pane
+    pub split: Split,
+    /// Size of new pane, number of cells or percentage e.g. `30%`
+    pub size: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Window {
+    pub name: Option<String>,
+    /// Working directory, relative path is resolved against session directory
+    pub cwd: Option<PathBuf>,
+    /// Any argument accepted by `select-layout`, applied after all panes are created
+    pub layout: Option<String>,
+    pub panes: Vec<Pane>,
+}
+
+/// Session with windows and panes, read from layout file or `[profiles.<name>]` table
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    /// Session name, default to layout or profile name
+    pub session: Option<String>,
+    /// Relative path is resolved against directory of layout file
+    pub start_directory: Option<PathBuf>,
+    pub windows: Vec<Window>,
+}
+
+impl Layout {
+    pub fn load(path: &Path) -> crate::Result<Self> {
+        let mut layout: Layout = toml::from_str(&fs::read_to_string(path)?)?;
+        let dir = layout_dir(&env::current_dir()?, path);
+        layout.start_directory = resolve_dir(dir.as_deref(), layout.start_directory.as_deref());
+        Ok(layout)
+    }
+
+    pub fn session_name<'a>(&'a self, default: &'a str) -> &'a str {
+        self.session.as_deref().unwrap_or(default)
+    }
+
+    /// Commands that create this layout, see [`build_commands`]
+    pub fn commands(&self, first_command: Vec<String>, v: Version) -> Vec<Vec<String>> {
+        build_commands(
+            first_command,
+            self.start_directory.as_deref(),
+            &self.windows,
+            v,
+        )
+    }
+}
+
+/// tmux doesn't allow `.` and `:` in session name
+pub fn sanitize_session_name(name: &str) -> String {
+    name.replace(&['.', ':'][..], "_")
+}
+
+/// Absolute directory of layout file, tmux resolves relative start directory against its own cwd
+fn layout_dir(cwd: &Path, path: &Path) -> Option<PathBuf> {
+    cwd.join(path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+}
+
+fn resolve_dir(base: Option<&Path>, dir: Option<&Path>) -> Option<PathBuf> {
+    match (base, dir.map(expand_tilde)) {
+        (Some(base), Some(dir)) => Some(base.join(dir)),
+        (None, Some(dir)) => Some(dir),
+        (base, None) => base.map(Path::to_path_buf),
+    }
+}
+
+/// tmux treats an argument ending with `;` as command separator unless it is escaped
+fn escape_separator(s: &str) -> String {
+    match s.strip_suffix(';') {
+        Some(rest) if !rest.ends_with('\\') => format!("{}\\;", rest),
+        _ => s.to_string(),
+    }
+}
+
+fn push_dir(cmd: &mut Vec<String>, dir: &Option<PathBuf>) {
+    if let Some(dir) = dir {
+        cmd.push("-c".to_string());
+        cmd.push(dir.to_string_lossy().into_owned());
+    }
+}
+
+fn push_size(cmd: &mut Vec<String>, size: &str, v: Version) {
+    match size.strip_suffix('%') {
+        // `-l` accepts percentage since tmux 3.1
+        Some(percentage) if v < Version::V31 => {
+            cmd.push("-p".to_string());
+            cmd.push(percentage.to_string());
+        }
+        _ => {
+            cmd.push("-l".to_string());
+            cmd.push(size.to_string());
+        }
+    }
+}
+
+fn push_send_command(commands: &mut Vec<Vec<String>>, command: &Option<String>) {
+    if let Some(command) = command {
+        let literal = escape_separator(command);
+        commands.push(vec!["send-keys".into(), "-l".into(), literal]);
+        commands.push(vec!["send-keys".into(), "Enter".into()]);
+    }
+}
+
+/// Build tmux commands that lay out `windows`
+///
+/// The first command is `first_command` (`new-session` or `new-window`) extended with
+/// directory and name of the first window, so the caller decides how the first window is created.
+/// Later commands rely on tmux making newly created window and pane current.
+pub fn build_commands(
+    first_command: Vec<String>,
+    start_directory: Option<&Path>,
+    windows: &[Window],
+    v: Version,
+) -> Vec<Vec<String>> {
+    let start_directory = start_directory.map(expand_tilde);
+    let default_window = [Window::default()];
+    let windows = if windows.is_empty() {
+        &default_window[..]
+    } else {
+        windows
+    };
+    let mut commands = Vec::new();
+    for (index, window) in windows.iter().enumerate() {
+        let window_dir = resolve_dir(start_directory.as_deref(), window.cwd.as_deref());
+        let default_pane = [Pane::default()];
+        let panes = if window.panes.is_empty() {
+            &default_pane[..]
+        } else {
+            &window.panes[..]
+        };
+        let first_pane = &panes[0];
+        let mut cmd = if index == 0 {
+            first_command.clone()
+        } else {
+            vec!["new-window".to_string()]
+        };
+        if let Some(ref name) = window.name {
+            cmd.push("-n".to_string());
+            cmd.push(name.clone());
+        }
+        push_dir(
+            &mut cmd,
+            &resolve_dir(window_dir.as_deref(), first_pane.cwd.as_deref()),
+        );
+        commands.push(cmd);
+        push_send_command(&mut commands, &first_pane.command);
+        for pane in &panes[1..] {
+            let mut cmd = vec!["split-window".to_string()];
+            cmd.push(match pane.split {
+                Split::Horizontal => "-h".to_string(),
+                Split::Vertical => "-v".to_string(),
+            });
+            if let Some(ref size) = pane.size {
+                push_size(&mut cmd, size, v);
+            }
+            push_dir(
+                &mut cmd,
+                &resolve_dir(window_dir.as_deref(), pane.cwd.as_deref()),
+            );
+            commands.push(cmd);
+            push_send_command(&mut commands, &pane.command);
+        }
+        if let Some(ref layout) = window.layout {
+            commands.push(vec!["select-layout".to_string(), layout.clone()]);
+        }
+        if panes.len() > 1 {
+            commands.push(vec![
+                "select-pane".to_string(),
+                "-t".to_string(),
+                "{top-left}".to_string(),
+            ]);
+        }
+    }
+    if windows.len() > 1 {
+        commands.push(vec![
+            "select-window".to_string(),
+            "-t".to_string(),
+            ":^".to_string(),
+        ]);
+    }
+    commands
+}
+
+/// Join commands into process arguments
+pub fn chain(commands: Vec<Vec<String>>) -> Vec<String> {
+    let mut args = Vec::new();
+    for (index, cmd) in commands.into_iter().enumerate() {
+        if index > 0 {
+            args.push(COMMAND_SEPARATOR.to_string());
+        }
+        args.extend(cmd);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(s: &str) -> Vec<String> {
+        s.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_build_commands() {
+        let windows: Vec<Window> = toml::from_str::<toml::Value>(
+            r#"
+            [[windows]]
+            name = "editor"
+            layout = "main-vertical"
+            panes = [
+                { command = "vim" },
+                { split = "horizontal", size = "30%", cwd = "tests", command = "cargo watch;" },
+            ]
+
+            [[windows]]
+            cwd = "/var/log"
+            "#,
+        )
+        .unwrap()["windows"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let first = words("new-session -s work");
+        let actual = build_commands(first, Some(Path::new("/src")), &windows, Version::V30);
+        let expect: Vec<Vec<String>> = vec![
+            words("new-session -s work -n editor -c /src"),
+            words("send-keys -l vim"),
+            words("send-keys Enter"),
+            words("split-window -h -p 30 -c /src/tests"),
+            vec!["send-keys".into(), "-l".into(), "cargo watch\\;".into()],
+            words("send-keys Enter"),
+            words("select-layout main-vertical"),
+            words("select-pane -t {top-left}"),
+            words("new-window -c /var/log"),
+            words("select-window -t :^"),
+        ];
+        assert_eq!(actual, expect);
+
+        let actual = build_commands(words("new-session"), None, &windows[..1], Version::V32);
+        assert_eq!(actual[3], words("split-window -h -l 30% -c tests"));
+    }
+
+    #[test]
+    fn test_layout_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("project.toml");
+        fs::write(
+            &path,
+            "start_directory = \"src\"\n[[windows]]\nname = \"test\"\n",
+        )
+        .unwrap();
+        let layout = Layout::load(&path).unwrap();
+        assert_eq!(layout.start_directory, Some(dir.join("src")));
+        assert_eq!(layout.session_name("project"), "project");
+        assert_eq!(layout.windows[0].name.as_deref(), Some("test"));
+        let cwd = Path::new("/home/user");
+        assert_eq!(
+            layout_dir(cwd, Path::new("work.toml")),
+            Some(PathBuf::from("/home/user"))
+        );
+        assert_eq!(
+            layout_dir(cwd, Path::new("/etc/nmk/work.toml")),
+            Some(PathBuf::from("/etc/nmk"))
+        );
+        assert_eq!(sanitize_session_name("nmk.rs:1"), "nmk_rs_1");
+    }
+
+    #[test]
+    fn test_chain() {
+        let actual = chain(vec![words("new-session"), words("send-keys Enter")]);
+        assert_eq!(actual, words("new-session ; send-keys Enter"));
+    }
+}
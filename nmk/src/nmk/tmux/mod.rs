@@ -1,2 +1,13 @@
 pub mod config;
+pub mod control;
+pub mod keys;
+pub mod layout;
+pub mod nested;
+pub mod profile;
+pub mod query;
+pub mod session;
+pub mod socket;
+pub mod status;
+pub mod theme;
+pub mod validate;
 pub mod version;
@@ -0,0 +1,90 @@
+use serde::Deserialize;
+
+/// Key table that has only the toggle key bound, other keys go to the pane
+pub const OFF_TABLE: &str = "off";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NestedConfig {
+    /// Use `prefix` when nmk is started inside another tmux or from SSH session in tmux
+    pub detect: bool,
+    /// Prefix of nested tmux, default to `C-a`, or `C-s` when outer tmux already uses `C-a`
+    pub prefix: Option<String>,
+    /// Key that switches to off mode and back
+    pub toggle_key: String,
+}
+
+impl Default for NestedConfig {
+    fn default() -> Self {
+        NestedConfig {
+            detect: true,
+            prefix: None,
+            toggle_key: String::from("M-F12"),
+        }
+    }
+}
+
+impl NestedConfig {
+    /// Prefix of nested tmux that differs from `outer` unless configured otherwise
+    pub fn prefix(&self, outer: &str) -> &str {
+        match self.prefix {
+            Some(ref prefix) => prefix,
+            None if outer == "C-a" => "C-s",
+            None => "C-a",
+        }
+    }
+}
+
+/// Check if client would run inside another terminal multiplexer
+///
+/// `tmux` is value of `$TMUX`, `term` is value of `$TERM`. Over SSH, `$TMUX` isn't
+/// forwarded but `TERM` of tmux or screen tells the terminal is a multiplexer pane.
+pub fn is_nested(tmux: Option<&str>, ssh_connection: Option<&str>, term: Option<&str>) -> bool {
+    let non_empty = |v: Option<&str>| v.is_some_and(|v| !v.is_empty());
+    if non_empty(tmux) {
+        return true;
+    }
+    non_empty(ssh_connection)
+        && term.is_some_and(|t| t.starts_with("tmux") || t.starts_with("screen"))
+}
+
+/// Text shown in status line while prefix is pressed, `C-a` is shown as `^A`
+pub fn prefix_label(prefix: &str) -> String {
+    match prefix.strip_prefix("C-") {
+        Some(key) if key.chars().count() == 1 => format!("^{}", key.to_uppercase()),
+        _ => prefix.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_nested() {
+        assert!(is_nested(Some("/tmp/tmux-0/default,1,0"), None, None));
+        assert!(!is_nested(Some(""), None, Some("xterm")));
+        assert!(is_nested(None, Some("::1 2 ::1 22"), Some("tmux-256color")));
+        assert!(is_nested(None, Some("::1 2 ::1 22"), Some("screen")));
+        assert!(!is_nested(
+            None,
+            Some("::1 2 ::1 22"),
+            Some("xterm-256color")
+        ));
+        assert!(!is_nested(None, None, Some("screen-256color")));
+    }
+
+    #[test]
+    fn test_prefix_label() {
+        assert_eq!(prefix_label("C-b"), "^B");
+        assert_eq!(prefix_label("C-a"), "^A");
+        assert_eq!(prefix_label("M-a"), "M-a");
+        let config = NestedConfig::default();
+        assert_eq!(config.prefix("C-b"), "C-a");
+        assert_eq!(config.prefix("C-a"), "C-s");
+        let config: NestedConfig = toml::from_str("prefix = \"C-q\"").unwrap();
+        assert_eq!(config.prefix("C-a"), "C-q");
+        assert_eq!(config.toggle_key, "M-F12");
+        assert!(config.detect);
+    }
+}
@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+use super::layout::Layout;
+
+/// Named workspace started by `nmk --profile <name>`, defined in `[profiles.<name>]` table
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// tmux socket name, default to `-L` option
+    pub socket: Option<String>,
+    /// Session created when it doesn't exist yet, session name default to profile name
+    #[serde(flatten)]
+    pub layout: Layout,
+}
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+const SEPARATOR: char = '\t';
+
+/// Object listed by a tmux `list-*` command with `-F` format
+pub trait Query: Sized {
+    /// `list-*` command that lists this object
+    const COMMAND: &'static str;
+    /// Format given to `-F`, fields are separated by tab
+    const FORMAT: &'static str;
+
+    /// Parse a line of output produced by [`Query::FORMAT`]
+    fn parse(line: &str) -> Option<Self>;
+
+    /// Parse all lines of output, malformed lines are skipped
+    fn parse_all(output: &str) -> Vec<Self> {
+        output.lines().filter_map(Self::parse).collect()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub name: String,
+    pub windows: u32,
+    /// Number of attached clients
+    pub attached: u32,
+    /// Seconds since unix epoch
+    pub created: u64,
+    /// Seconds since unix epoch
+    pub activity: u64,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct WindowInfo {
+    pub session: String,
+    pub id: String,
+    pub index: u32,
+    pub name: String,
+    pub active: bool,
+    pub panes: u32,
+    pub layout: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct PaneInfo {
+    pub session: String,
+    pub window_index: u32,
+    pub id: String,
+    pub index: u32,
+    pub active: bool,
+    pub pid: u32,
+    pub width: u32,
+    pub height: u32,
+    pub current_command: String,
+    pub current_path: PathBuf,
+}
+
+fn flag(s: &str) -> Option<bool> {
+    match s {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+impl Query for SessionInfo {
+    const COMMAND: &'static str = "list-sessions";
+    const FORMAT: &'static str = "#{session_id}\t#{session_windows}\t#{session_attached}\t#{session_created}\t#{session_activity}\t#{session_name}";
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut iter = line.splitn(6, SEPARATOR);
+        Some(SessionInfo {
+            id: iter.next()?.to_string(),
+            windows: iter.next()?.parse().ok()?,
+            attached: iter.next()?.parse().ok()?,
+            created: iter.next()?.parse().ok()?,
+            activity: iter.next()?.parse().ok()?,
+            name: iter.next()?.to_string(),
+        })
+    }
+}
+
+impl Query for WindowInfo {
+    const COMMAND: &'static str = "list-windows";
+    const FORMAT: &'static str = "#{session_name}\t#{window_id}\t#{window_index}\t#{window_active}\t#{window_panes}\t#{window_layout}\t#{window_name}";
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut iter = line.splitn(7, SEPARATOR);
+        Some(WindowInfo {
+            session: iter.next()?.to_string(),
+            id: iter.next()?.to_string(),
+            index: iter.next()?.parse().ok()?,
+            active: flag(iter.next()?)?,
+            panes: iter.next()?.parse().ok()?,
+            layout: iter.next()?.to_string(),
+            name: iter.next()?.to_string(),
+        })
+    }
+}
+
+impl Query for PaneInfo {
+    const COMMAND: &'static str = "list-panes";
+    const FORMAT: &'static str = "#{session_name}\t#{window_index}\t#{pane_id}\t#{pane_index}\t#{pane_active}\t#{pane_pid}\t#{pane_width}\t#{pane_height}\t#{pane_current_command}\t#{pane_current_path}";
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut iter = line.splitn(10, SEPARATOR);
+        Some(PaneInfo {
+            session: iter.next()?.to_string(),
+            window_index: iter.next()?.parse().ok()?,
+            id: iter.next()?.to_string(),
+            index: iter.next()?.parse().ok()?,
+            active: flag(iter.next()?)?,
+            pid: iter.next()?.parse().ok()?,
+            width: iter.next()?.parse().ok()?,
+            height: iter.next()?.parse().ok()?,
+            current_command: iter.next()?.to_string(),
+            current_path: PathBuf::from(iter.next()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let sessions = SessionInfo::parse_all(
+            "$0\t2\t1\t1625063101\t1625063200\tmain\n$1\t1\t0\t1625063300\t1625063300\tmy\twork\n",
+        );
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].attached, 1);
+        assert_eq!(sessions[1].name, "my\twork");
+
+        let windows = WindowInfo::parse_all("main\t@3\t1\t1\t2\tb25f,80x24,0,0,2\tlogs\nbroken\n");
+        assert_eq!(
+            windows,
+            vec![WindowInfo {
+                session: "main".to_string(),
+                id: "@3".to_string(),
+                index: 1,
+                name: "logs".to_string(),
+                active: true,
+                panes: 2,
+                layout: "b25f,80x24,0,0,2".to_string(),
+            }]
+        );
+
+        let pane = PaneInfo::parse("main\t1\t%4\t0\t0\t1234\t80\t23\tzsh\t/var/log").unwrap();
+        assert_eq!(pane.id, "%4");
+        assert!(!pane.active);
+        assert_eq!(pane.current_path, PathBuf::from("/var/log"));
+        let json = serde_json::to_value(&pane).unwrap();
+        assert_eq!(json["window_index"], 1);
+    }
+}
@@ -0,0 +1,309 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::home::NmkPath;
+use crate::setup::write_atomic;
+
+use super::layout::{Layout, Pane, Split, Window};
+
+const SEPARATOR: char = '\t';
+const SHELLS: &[&str] = &["bash", "fish", "sh", "zsh"];
+const RESTORE_COMMANDS: &[&str] = &[
+    "htop", "less", "man", "nvim", "tail", "top", "vi", "vim", "watch",
+];
+
+/// Format for `list-panes -a -F`, fields are separated by tab
+pub const LIST_PANES_FORMAT: &str = "#{session_name}\t#{window_index}\t#{window_name}\t#{window_layout}\t#{pane_index}\t#{pane_id}\t#{pane_pid}\t#{pane_current_path}\t#{pane_current_command}";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Periodically save sessions from tmux status line
+    pub autosave: bool,
+    /// Minimum minutes between automatic saves
+    pub autosave_interval: u64,
+    /// Restore saved sessions when entrypoint starts a new tmux server
+    pub restore_on_start: bool,
+    /// Save pane contents and print them back on restore
+    pub scrollback: bool,
+    /// Programs that are started again on restore, other programs are dropped
+    pub restore_commands: Vec<String>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            autosave: false,
+            autosave_interval: 15,
+            restore_on_start: true,
+            scrollback: false,
+            restore_commands: RESTORE_COMMANDS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub created: String,
+    pub sessions: Vec<SessionState>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SessionState {
+    pub name: String,
+    pub windows: Vec<WindowState>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WindowState {
+    pub index: u32,
+    pub name: String,
+    /// Value of `#{window_layout}`
+    pub layout: String,
+    pub panes: Vec<PaneState>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PaneState {
+    pub index: u32,
+    pub cwd: PathBuf,
+    /// Program running in foreground, None if it is a shell
+    pub command: Option<String>,
+    pub scrollback: Option<PathBuf>,
+}
+
+/// A line of `list-panes` output
+#[derive(Debug)]
+pub struct PaneLine<'a> {
+    pub session: &'a str,
+    pub window_index: u32,
+    pub window_name: &'a str,
+    pub window_layout: &'a str,
+    pub pane_index: u32,
+    pub pane_id: &'a str,
+    pub pane_pid: u32,
+    pub current_path: &'a str,
+    pub current_command: &'a str,
+}
+
+impl<'a> PaneLine<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let mut iter = line.split(SEPARATOR);
+        Some(PaneLine {
+            session: iter.next()?,
+            window_index: iter.next()?.parse().ok()?,
+            window_name: iter.next()?,
+            window_layout: iter.next()?,
+            pane_index: iter.next()?.parse().ok()?,
+            pane_id: iter.next()?,
+            pane_pid: iter.next()?.parse().ok()?,
+            current_path: iter.next()?,
+            current_command: iter.next()?,
+        })
+    }
+
+    pub fn is_shell(&self) -> bool {
+        SHELLS.contains(&self.current_command.trim_start_matches('-'))
+    }
+
+    /// File name for scrollback of this pane
+    pub fn scrollback_name(&self) -> String {
+        format!(
+            "{}-{}-{}.txt",
+            self.session.replace('/', "_"),
+            self.window_index,
+            self.pane_index
+        )
+    }
+}
+
+/// Full command line of the foreground program in pane, Linux only
+///
+/// `pane_pid` is the shell, its first child is the program started from it.
+pub fn foreground_command(pane_pid: u32) -> Option<String> {
+    let children = fs::read_to_string(format!("/proc/{0}/task/{0}/children", pane_pid)).ok()?;
+    let child = children.split_whitespace().next()?;
+    let cmdline = fs::read(format!("/proc/{}/cmdline", child)).ok()?;
+    let args: Vec<_> = cmdline
+        .split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect();
+    if args.is_empty() {
+        None
+    } else {
+        Some(shell_words::join(args.iter().map(|s| s.as_ref())))
+    }
+}
+
+impl Snapshot {
+    /// Group panes into sessions and windows, `command_of` resolve command of non-shell pane
+    /// and `scrollback_of` return where pane content was saved
+    pub fn from_panes<C, S>(
+        created: String,
+        panes: &[PaneLine],
+        mut command_of: C,
+        mut scrollback_of: S,
+    ) -> Self
+    where
+        C: FnMut(&PaneLine) -> Option<String>,
+        S: FnMut(&PaneLine) -> Option<PathBuf>,
+    {
+        let mut sessions: Vec<SessionState> = Vec::new();
+        for p in panes {
+            if sessions.last().is_none_or(|s| s.name != p.session) {
+                sessions.push(SessionState {
+                    name: p.session.to_string(),
+                    windows: Vec::new(),
+                });
+            }
+            let windows = &mut sessions.last_mut().expect("session was pushed").windows;
+            if windows.last().is_none_or(|w| w.index != p.window_index) {
+                windows.push(WindowState {
+                    index: p.window_index,
+                    name: p.window_name.to_string(),
+                    layout: p.window_layout.to_string(),
+                    panes: Vec::new(),
+                });
+            }
+            let command = if p.is_shell() { None } else { command_of(p) };
+            let window = windows.last_mut().expect("window was pushed");
+            window.panes.push(PaneState {
+                index: p.pane_index,
+                cwd: PathBuf::from(p.current_path),
+                command,
+                scrollback: scrollback_of(p),
+            });
+        }
+        Snapshot { created, sessions }
+    }
+
+    pub fn load(path: &Path) -> crate::Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> crate::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = toml::to_string_pretty(self)?;
+        write_atomic(path, contents.as_bytes(), 0o600)?;
+        Ok(())
+    }
+
+    /// Convert to layouts that recreate each session
+    ///
+    /// Only programs in `restore_commands` are started again.
+    pub fn to_layouts(&self, restore_commands: &[String]) -> Vec<Layout> {
+        let is_restorable = |command: &str| {
+            shell_words::split(command)
+                .ok()
+                .and_then(|args| args.into_iter().next())
+                .is_some_and(|program| {
+                    let name = Path::new(&program)
+                        .file_name()
+                        .map(|s| s.to_string_lossy().into_owned());
+                    name.is_some_and(|name| restore_commands.contains(&name))
+                })
+        };
+        let restore_pane = |pane: &PaneState| {
+            let mut commands = Vec::new();
+            if let Some(ref scrollback) = pane.scrollback {
+                // leading space keeps this command out of shell history
+                let path = scrollback.to_string_lossy();
+                commands.push(format!(" cat -- {}", shell_words::quote(&path)));
+            }
+            if let Some(ref command) = pane.command {
+                if is_restorable(command) {
+                    commands.push(command.clone());
+                }
+            }
+            Pane {
+                cwd: Some(pane.cwd.clone()),
+                command: Some(commands.join("; ")).filter(|s| !s.is_empty()),
+                split: Split::Vertical,
+                size: None,
+            }
+        };
+        self.sessions
+            .iter()
+            .map(|session| Layout {
+                session: Some(session.name.clone()),
+                start_directory: None,
+                windows: session
+                    .windows
+                    .iter()
+                    .map(|w| Window {
+                        name: Some(w.name.clone()),
+                        cwd: None,
+                        layout: Some(w.layout.clone()),
+                        panes: w.panes.iter().map(restore_pane).collect(),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Snapshot file of tmux server on `socket`
+pub fn snapshot_path(nmk_path: &NmkPath, socket: &str) -> PathBuf {
+    nmk_path.sessions().join(format!("{}.toml", socket))
+}
+
+/// Directory of pane contents of tmux server on `socket`
+pub fn scrollback_dir(nmk_path: &NmkPath, socket: &str) -> PathBuf {
+    nmk_path.sessions().join(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST_PANES: &str =
+        "main\t0\teditor\tb25f,80x24,0,0[80x12,0,0,1,80x11,0,13,2]\t0\t%1\t100\t/src\tvim\n\
+        main\t0\teditor\tb25f,80x24,0,0[80x12,0,0,1,80x11,0,13,2]\t1\t%2\t101\t/src/tests\tzsh\n\
+        main\t1\tlogs\tc1f3,80x24,0,0,3\t0\t%3\t102\t/var/log\ttail\n\
+        work\t0\tzsh\tc1f4,80x24,0,0,4\t0\t%4\t103\t/home\tpython3";
+
+    #[test]
+    fn test_snapshot() {
+        let panes: Vec<_> = LIST_PANES.lines().flat_map(PaneLine::parse).collect();
+        assert_eq!(panes.len(), 4);
+        let snapshot = Snapshot::from_panes(
+            String::new(),
+            &panes,
+            |p| match p.current_command {
+                "vim" => Some("vim Cargo.toml".to_string()),
+                "tail" => Some("tail -f syslog".to_string()),
+                _ => Some(p.current_command.to_string()),
+            },
+            |p| Some(PathBuf::from("/s").join(p.scrollback_name())).filter(|_| p.pane_index == 1),
+        );
+        assert_eq!(snapshot.sessions.len(), 2);
+        assert_eq!(snapshot.sessions[0].windows.len(), 2);
+        assert_eq!(snapshot.sessions[0].windows[0].panes[1].command, None);
+
+        let restore = SessionConfig::default().restore_commands;
+        let layouts = snapshot.to_layouts(&restore);
+        let editor = &layouts[0].windows[0];
+        assert_eq!(editor.layout.as_deref(), Some(panes[0].window_layout));
+        assert_eq!(editor.panes[0].command.as_deref(), Some("vim Cargo.toml"));
+        assert_eq!(
+            editor.panes[1].command.as_deref(),
+            Some(" cat -- /s/main-0-1.txt")
+        );
+        assert_eq!(editor.panes[1].cwd, Some(PathBuf::from("/src/tests")));
+        assert_eq!(
+            layouts[0].windows[1].panes[0].command.as_deref(),
+            Some("tail -f syslog")
+        );
+        // python3 is not in restore commands
+        assert_eq!(layouts[1].windows[0].panes[0].command, None);
+
+        let serialized = toml::to_string_pretty(&snapshot).unwrap();
+        let parsed: Snapshot = toml::from_str(&serialized).unwrap();
+        assert_eq!(parsed.sessions[1].name, "work");
+    }
+}
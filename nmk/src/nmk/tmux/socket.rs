@@ -0,0 +1,155 @@
+use std::env;
+use std::fmt;
+use std::path::Path;
+
+use crate::container;
+use crate::platform;
+
+/// Placeholders of socket name template and what they expand to
+pub const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("host", "short hostname"),
+    (
+        "container",
+        "container id, or short hostname outside container",
+    ),
+    (
+        "project",
+        "name of nearest directory containing .git, or current directory",
+    ),
+];
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum SocketTemplateError {
+    Unclosed(String),
+    UnknownPlaceholder(String),
+}
+
+impl fmt::Display for SocketTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketTemplateError::Unclosed(template) => {
+                write!(f, "unclosed {{ in socket name {}", template)
+            }
+            SocketTemplateError::UnknownPlaceholder(name) => {
+                let known: Vec<&str> = PLACEHOLDERS.iter().map(|p| p.0).collect();
+                write!(
+                    f,
+                    "unknown placeholder {{{}}} in socket name, expected one of {}",
+                    name,
+                    known.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SocketTemplateError {}
+
+/// Replace characters that don't belong to a socket file name
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Expand `{name}` placeholders of `template` with `lookup`, values are sanitized
+pub fn expand<F>(template: &str, lookup: F) -> Result<String, SocketTemplateError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| SocketTemplateError::Unclosed(template.to_string()))?;
+        let name = &rest[start + 1..start + end];
+        let value = lookup(name)
+            .ok_or_else(|| SocketTemplateError::UnknownPlaceholder(name.to_string()))?;
+        result.push_str(&sanitize(&value));
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn short_hostname() -> String {
+    let hostname = platform::hostname();
+    match hostname.split('.').next() {
+        Some(short) if !short.is_empty() => short.to_string(),
+        _ => hostname,
+    }
+}
+
+/// Name of nearest ancestor of `dir` containing `.git`, or `dir` itself
+fn project_name(dir: &Path) -> Option<String> {
+    let root = dir
+        .ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(dir);
+    root.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+fn lookup(name: &str) -> Option<String> {
+    match name {
+        "host" => Some(short_hostname()),
+        "container" => Some(container::container_name().unwrap_or_else(short_hostname)),
+        "project" => Some(
+            env::current_dir()
+                .ok()
+                .and_then(|dir| project_name(&dir))
+                .unwrap_or_else(|| "root".to_string()),
+        ),
+        _ => None,
+    }
+}
+
+/// Socket name from template such as `nmk-{host}`, names without placeholder are kept
+pub fn resolve(template: &str) -> Result<String, SocketTemplateError> {
+    expand(template, lookup)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let lookup = |name: &str| match name {
+            "host" => Some("box".to_string()),
+            "project" => Some("my app/x".to_string()),
+            _ => None,
+        };
+        assert_eq!(expand("nmk", lookup).unwrap(), "nmk");
+        assert_eq!(expand("nmk-{host}", lookup).unwrap(), "nmk-box");
+        assert_eq!(expand("{host}-{project}", lookup).unwrap(), "box-my_app_x");
+        assert_eq!(
+            expand("nmk-{host", lookup),
+            Err(SocketTemplateError::Unclosed("nmk-{host".to_string()))
+        );
+        assert_eq!(
+            expand("nmk-{user}", lookup),
+            Err(SocketTemplateError::UnknownPlaceholder("user".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_project_name() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        let nested = base.join("repo").join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(project_name(&nested).as_deref(), Some("bin"));
+        fs::create_dir(base.join("repo").join(".git")).unwrap();
+        assert_eq!(project_name(&nested).as_deref(), Some("repo"));
+        assert_eq!(project_name(Path::new("/")), None);
+    }
+}
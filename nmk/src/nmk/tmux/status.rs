@@ -0,0 +1,243 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::container;
+use crate::platform;
+use crate::setup::write_atomic;
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    PartialEq,
+    strum::AsStaticStr,
+    strum::EnumString,
+    strum::EnumVariantNames,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Segment {
+    /// Branch of repository at pane current path, `*` is appended if worktree is dirty
+    Git,
+    /// 1 minute load average
+    Load,
+    /// Percentage of memory in use
+    Memory,
+    /// Capacity of the first battery, `+` is appended while charging
+    Battery,
+    /// Short id of container
+    Container,
+    Hostname,
+    /// Shown only when tmux server was started from SSH session
+    Ssh,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// Segments put after session name in `status-left`
+    pub left: Vec<Segment>,
+    /// Segments put before clock in `status-right`
+    pub right: Vec<Segment>,
+    /// Seconds a segment output is reused before it is computed again
+    pub cache_seconds: u64,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        StatusConfig {
+            left: Vec::new(),
+            right: Vec::new(),
+            cache_seconds: 5,
+        }
+    }
+}
+
+impl Segment {
+    pub fn as_str(&self) -> &'static str {
+        strum::AsStaticRef::as_static(self)
+    }
+
+    /// Segment output depends on pane current path
+    pub fn needs_path(self) -> bool {
+        self == Segment::Git
+    }
+
+    /// Compute segment text, `None` if there is nothing to show
+    pub fn render(self, path: Option<&Path>) -> Option<String> {
+        match self {
+            Segment::Git => git(path?),
+            Segment::Load => parse_load(&fs::read_to_string("/proc/loadavg").ok()?),
+            Segment::Memory => parse_memory(&fs::read_to_string("/proc/meminfo").ok()?),
+            Segment::Battery => battery(Path::new(POWER_SUPPLY)),
+            Segment::Container => container::container_name(),
+            Segment::Hostname => Some(platform::hostname()),
+            Segment::Ssh => env::var_os("SSH_CONNECTION").map(|_| "ssh".to_string()),
+        }
+    }
+}
+
+fn git_output(path: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+fn git(path: &Path) -> Option<String> {
+    let branch = git_output(path, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let status = git_output(path, &["status", "--porcelain", "--untracked-files=no"])?;
+    let dirty = if status.is_empty() { "" } else { "*" };
+    Some(format!("{}{}", branch, dirty))
+}
+
+fn parse_load(loadavg: &str) -> Option<String> {
+    loadavg.split_whitespace().next().map(String::from)
+}
+
+fn parse_memory(meminfo: &str) -> Option<String> {
+    let field = |name: &str| -> Option<u64> {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+    let total = field("MemTotal").filter(|&t| t > 0)?;
+    let available = field("MemAvailable")?;
+    let used = total.saturating_sub(available) * 100 / total;
+    Some(format!("mem {}%", used))
+}
+
+fn battery(power_supply: &Path) -> Option<String> {
+    let read = |dir: &Path, name: &str| {
+        fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|s| s.trim().to_string())
+    };
+    let mut dirs: Vec<_> = fs::read_dir(power_supply)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+    let dir = dirs
+        .iter()
+        .find(|d| read(d, "type").as_deref() == Some("Battery"))?;
+    let capacity = read(dir, "capacity")?;
+    let charging = if read(dir, "status").as_deref() == Some("Charging") {
+        "+"
+    } else {
+        ""
+    };
+    Some(format!("{}%{}", capacity, charging))
+}
+
+/// Cache file of segment, paths are hashed so file name stays short
+pub fn cache_path(cache_dir: &Path, segment: Segment, path: Option<&Path>) -> PathBuf {
+    match path {
+        Some(path) => {
+            let mut hasher = DefaultHasher::new();
+            path.hash(&mut hasher);
+            cache_dir.join(format!("{}-{:016x}", segment.as_str(), hasher.finish()))
+        }
+        None => cache_dir.join(segment.as_str()),
+    }
+}
+
+/// Render segment or reuse output cached within `ttl`, empty string if nothing to show
+pub fn render_cached(
+    cache_dir: &Path,
+    ttl: Duration,
+    segment: Segment,
+    path: Option<&Path>,
+) -> String {
+    let path = path.filter(|_| segment.needs_path());
+    let cache = cache_path(cache_dir, segment, path);
+    let is_fresh = cache
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .is_some_and(|age| age < ttl);
+    if is_fresh {
+        if let Ok(text) = fs::read_to_string(&cache) {
+            return text;
+        }
+    }
+    let text = segment.render(path).unwrap_or_default();
+    if fs::create_dir_all(cache_dir).is_ok() {
+        // a failed write only costs a recomputation on next call
+        let _ = write_atomic(&cache, text.as_bytes(), 0o600);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_load("0.52 0.58 0.59 1/467 4221\n").as_deref(),
+            Some("0.52")
+        );
+        let meminfo = "MemTotal:       16000000 kB\nMemFree:         1000000 kB\nMemAvailable:    4000000 kB\n";
+        assert_eq!(parse_memory(meminfo).as_deref(), Some("mem 75%"));
+        assert_eq!(parse_memory("MemTotal: 0 kB\n"), None);
+        let config: StatusConfig = toml::from_str("right = [\"git\", \"load\"]").unwrap();
+        assert_eq!(config.right, vec![Segment::Git, Segment::Load]);
+        assert_eq!(config.cache_seconds, 5);
+    }
+
+    #[test]
+    fn test_battery_and_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let bat = dir.join("power_supply").join("BAT0");
+        fs::create_dir_all(&bat).unwrap();
+        fs::create_dir_all(dir.join("power_supply").join("AC")).unwrap();
+        fs::write(dir.join("power_supply/AC/type"), "Mains\n").unwrap();
+        fs::write(bat.join("type"), "Battery\n").unwrap();
+        fs::write(bat.join("capacity"), "87\n").unwrap();
+        fs::write(bat.join("status"), "Charging\n").unwrap();
+        assert_eq!(battery(&dir.join("power_supply")).as_deref(), Some("87%+"));
+
+        let cache_dir = dir.join("cache");
+        let ttl = Duration::from_secs(60);
+        let hostname = render_cached(&cache_dir, ttl, Segment::Hostname, None);
+        assert_eq!(hostname, platform::hostname());
+        fs::write(cache_dir.join("hostname"), "cached").unwrap();
+        assert_eq!(
+            render_cached(&cache_dir, ttl, Segment::Hostname, None),
+            "cached"
+        );
+        let a = cache_path(&cache_dir, Segment::Git, Some(Path::new("/a")));
+        let b = cache_path(&cache_dir, Segment::Git, Some(Path::new("/b")));
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,474 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::home::NmkPath;
+
+pub const DEFAULT_THEME: &str = "default";
+/// Theme used when terminal supports only 8 colours and no theme is configured
+pub const PLAIN_THEME: &str = "plain";
+
+const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("default", include_str!("themes/default.toml")),
+    ("gruvbox", include_str!("themes/gruvbox.toml")),
+    ("nord", include_str!("themes/nord.toml")),
+    ("plain", include_str!("themes/plain.toml")),
+    ("solarized", include_str!("themes/solarized.toml")),
+];
+
+const BASIC_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// RGB of basic colours, as xterm shows them
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ColorDepth {
+    Basic,
+    Indexed256,
+    TrueColor,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Color {
+    Default,
+    /// One of 8 basic colours, 0 is black and 7 is white
+    Basic(u8),
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn nearest_cube_level(v: u8) -> usize {
+    (0..CUBE_LEVELS.len())
+        .min_by_key(|&i| (i32::from(CUBE_LEVELS[i]) - i32::from(v)).abs())
+        .unwrap_or_default()
+}
+
+impl Color {
+    fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Default => None,
+            Color::Basic(n) => Some(BASIC_RGB[usize::from(n)]),
+            Color::Indexed(n @ 0..=15) => Some(BASIC_RGB[usize::from(n)]),
+            Color::Indexed(n @ 16..=231) => {
+                let n = n - 16;
+                let level = |i: u8| CUBE_LEVELS[usize::from(i)];
+                Some((level(n / 36), level(n / 6 % 6), level(n % 6)))
+            }
+            Color::Indexed(n) => {
+                let gray = 8 + (n - 232) * 10;
+                Some((gray, gray, gray))
+            }
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+        }
+    }
+
+    /// Closest colour that terminal of `depth` can show
+    pub fn downgrade(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (Color::Default, _) | (Color::Basic(_), _) => self,
+            (_, ColorDepth::TrueColor) | (Color::Indexed(_), ColorDepth::Indexed256) => self,
+            (Color::Rgb(r, g, b), ColorDepth::Indexed256) => {
+                let (ri, gi, bi) = (
+                    nearest_cube_level(r),
+                    nearest_cube_level(g),
+                    nearest_cube_level(b),
+                );
+                let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+                let cube_index = 16 + 36 * ri + 6 * gi + bi;
+                let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+                let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+                let gray = 8 + gray_index * 10;
+                if distance((r, g, b), (gray, gray, gray)) < distance((r, g, b), cube) {
+                    Color::Indexed(232 + gray_index)
+                } else {
+                    Color::Indexed(cube_index as u8)
+                }
+            }
+            (_, ColorDepth::Basic) => {
+                let rgb = self.to_rgb().expect("only default colour has no rgb");
+                let nearest = (0..8u8)
+                    .min_by_key(|&n| distance(rgb, BASIC_RGB[usize::from(n)]))
+                    .unwrap_or_default();
+                Color::Basic(nearest)
+            }
+        }
+    }
+
+    /// SGR parameters to set this colour as foreground, background is foreground + 10
+    fn sgr(self) -> String {
+        match self {
+            Color::Default => "39".to_string(),
+            Color::Basic(n) => (30 + n).to_string(),
+            Color::Indexed(n) => format!("38;5;{}", n),
+            Color::Rgb(r, g, b) => format!("38;2;{};{};{}", r, g, b),
+        }
+    }
+
+    fn sgr_background(self) -> String {
+        match self {
+            Color::Default => "49".to_string(),
+            Color::Basic(n) => (40 + n).to_string(),
+            _ => format!("4{}", &self.sgr()[1..]),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        if lower == "default" {
+            return Ok(Color::Default);
+        }
+        if let Some(n) = BASIC_NAMES.iter().position(|&name| name == lower) {
+            return Ok(Color::Basic(n as u8));
+        }
+        let indexed = lower
+            .strip_prefix("colour")
+            .or_else(|| lower.strip_prefix("color"));
+        if let Some(n) = indexed.and_then(|n| n.parse().ok()) {
+            return Ok(Color::Indexed(n));
+        }
+        let rgb = lower
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+        match rgb {
+            Some(v) => Ok(Color::Rgb((v >> 16) as u8, (v >> 8) as u8, v as u8)),
+            None => Err(format!("invalid colour: {}", s)),
+        }
+    }
+}
+
+/// tmux colour name
+impl Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Color::Default => write!(f, "default"),
+            Color::Basic(n) => write!(f, "{}", BASIC_NAMES[usize::from(n)]),
+            Color::Indexed(n) => write!(f, "colour{}", n),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Style {
+    #[serde(with = "optional_color")]
+    pub fg: Option<Color>,
+    #[serde(with = "optional_color")]
+    pub bg: Option<Color>,
+}
+
+mod optional_color {
+    use serde::{Deserialize, Deserializer};
+
+    use super::Color;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Color>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|s| s.parse().map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+impl Style {
+    fn is_empty(&self) -> bool {
+        self.fg.is_none() && self.bg.is_none()
+    }
+
+    /// tmux style string e.g. `fg=colour7,bg=default`
+    pub fn to_tmux(&self, depth: ColorDepth) -> String {
+        let fg = self.fg.map(|c| format!("fg={}", c.downgrade(depth)));
+        let bg = self.bg.map(|c| format!("bg={}", c.downgrade(depth)));
+        fg.into_iter().chain(bg).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Colours of tmux by their role, missing roles keep tmux defaults
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub description: Option<String>,
+    /// Status line
+    pub status: Style,
+    /// Window names in status line
+    pub window: Style,
+    pub current_window: Style,
+    /// Pane title in status line
+    #[serde(with = "optional_color")]
+    pub title: Option<Color>,
+    #[serde(with = "optional_color")]
+    pub border: Option<Color>,
+    #[serde(with = "optional_color")]
+    pub active_border: Option<Color>,
+    /// Command prompt and messages
+    pub message: Style,
+    /// Copy mode selection and choose mode
+    pub mode: Style,
+    #[serde(with = "optional_color")]
+    pub display_panes: Option<Color>,
+    #[serde(with = "optional_color")]
+    pub display_panes_active: Option<Color>,
+    #[serde(with = "optional_color")]
+    pub clock: Option<Color>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ThemeSource {
+    Builtin,
+    /// `$NMK_HOME/themes/<name>.toml`, it overrides builtin theme of the same name
+    User,
+}
+
+impl Theme {
+    pub fn parse(s: &str) -> crate::Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Find theme by name in user theme directory, then builtin themes
+    pub fn load(nmk_path: &NmkPath, name: &str) -> crate::Result<Option<Self>> {
+        let path = user_theme_path(nmk_path, name);
+        if path.exists() {
+            return Ok(Some(Self::parse(&fs::read_to_string(path)?)?));
+        }
+        match BUILTIN_THEMES.iter().find(|(n, _)| *n == name) {
+            Some((_, contents)) => Ok(Some(Self::parse(contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (_, contents) = BUILTIN_THEMES.iter().find(|(n, _)| *n == name)?;
+        Some(Self::parse(contents).expect("builtin theme must be valid"))
+    }
+
+    /// Command, option and its value, `None` if theme doesn't set this option
+    fn options(&self, depth: ColorDepth) -> Vec<(&'static str, &'static str, Option<String>)> {
+        let style = |s: &Style| Some(s.to_tmux(depth)).filter(|_| !s.is_empty());
+        let color = |c: Option<Color>| c.map(|c| c.downgrade(depth).to_string());
+        let fg = |c: Option<Color>| color(c).map(|c| format!("fg={}", c));
+        vec![
+            ("set-option", "status-style", style(&self.status)),
+            (
+                "set-window-option",
+                "window-status-style",
+                style(&self.window),
+            ),
+            (
+                "set-window-option",
+                "window-status-current-style",
+                style(&self.current_window),
+            ),
+            ("set-option", "message-style", style(&self.message)),
+            ("set-window-option", "mode-style", style(&self.mode)),
+            ("set-option", "pane-border-style", fg(self.border)),
+            (
+                "set-option",
+                "pane-active-border-style",
+                fg(self.active_border),
+            ),
+            (
+                "set-option",
+                "display-panes-colour",
+                color(self.display_panes),
+            ),
+            (
+                "set-option",
+                "display-panes-active-colour",
+                color(self.display_panes_active),
+            ),
+            ("set-window-option", "clock-mode-colour", color(self.clock)),
+        ]
+    }
+
+    /// tmux commands that apply this theme
+    pub fn render(&self, w: &mut dyn Write, depth: ColorDepth) -> io::Result<()> {
+        for (command, option, value) in self.options(depth) {
+            if let Some(value) = value {
+                // quoted since `#rrggbb` at start of a word is a comment in tmux config
+                writeln!(w, r#"{} -g {} "{}""#, command, option, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// tmux commands that restore default value of every option set by themes,
+    /// so switching theme on running server doesn't keep colours of previous theme
+    pub fn render_reset(w: &mut dyn Write) -> io::Result<()> {
+        for (command, option, _) in Theme::default().options(ColorDepth::Basic) {
+            writeln!(w, "{} -gu {}", command, option)?;
+        }
+        Ok(())
+    }
+
+    /// Swatches of every role coloured with ANSI escape sequences
+    pub fn preview(&self, depth: ColorDepth) -> String {
+        let swatch = |name: &str, style: &Style| {
+            let mut sgr = Vec::new();
+            if let Some(fg) = style.fg {
+                sgr.push(fg.downgrade(depth).sgr());
+            }
+            if let Some(bg) = style.bg {
+                sgr.push(bg.downgrade(depth).sgr_background());
+            }
+            format!(
+                "\x1b[{}m {:<22}\x1b[0m {}\n",
+                sgr.join(";"),
+                name,
+                style.to_tmux(depth)
+            )
+        };
+        let fg_only = |c: Option<Color>| Style { fg: c, bg: None };
+        let roles = [
+            ("status", self.status.clone()),
+            ("window", self.window.clone()),
+            ("current_window", self.current_window.clone()),
+            ("title", fg_only(self.title)),
+            ("border", fg_only(self.border)),
+            ("active_border", fg_only(self.active_border)),
+            ("message", self.message.clone()),
+            ("mode", self.mode.clone()),
+            ("display_panes", fg_only(self.display_panes)),
+            ("display_panes_active", fg_only(self.display_panes_active)),
+            ("clock", fg_only(self.clock)),
+        ];
+        roles
+            .iter()
+            .map(|(name, style)| swatch(name, style))
+            .collect()
+    }
+}
+
+/// Name of theme used when none is configured
+pub fn default_name(depth: ColorDepth) -> &'static str {
+    if depth == ColorDepth::Basic {
+        PLAIN_THEME
+    } else {
+        DEFAULT_THEME
+    }
+}
+
+/// Theme `name` or the default one, falling back to builtin theme if it can't be loaded
+pub fn resolve(nmk_path: &NmkPath, name: Option<&str>, depth: ColorDepth) -> Theme {
+    let name = name.unwrap_or_else(|| default_name(depth));
+    match Theme::load(nmk_path, name) {
+        Ok(Some(theme)) => return theme,
+        Ok(None) => warn!("Theme {} not found, using default theme", name),
+        Err(e) => warn!("Failed to load theme {}: {}", name, e),
+    }
+    Theme::builtin(default_name(depth)).expect("missing builtin theme")
+}
+
+pub fn user_theme_path(nmk_path: &NmkPath, name: &str) -> PathBuf {
+    nmk_path.themes().join(format!("{}.toml", name))
+}
+
+/// Names of builtin and user themes, sorted
+pub fn list(nmk_path: &NmkPath) -> Vec<(String, ThemeSource)> {
+    let mut themes: Vec<_> = BUILTIN_THEMES
+        .iter()
+        .map(|(name, _)| (name.to_string(), ThemeSource::Builtin))
+        .collect();
+    let user_themes = fs::read_dir(nmk_path.themes())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|p| Some(p.file_stem()?.to_string_lossy().into_owned()));
+    for name in user_themes {
+        themes.retain(|(n, _)| *n != name);
+        themes.push((name, ThemeSource::User));
+    }
+    themes.sort_by(|a, b| a.0.cmp(&b.0));
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!("default".parse(), Ok(Color::Default));
+        assert_eq!("Black".parse(), Ok(Color::Basic(0)));
+        assert_eq!("colour51".parse(), Ok(Color::Indexed(51)));
+        assert_eq!("color51".parse(), Ok(Color::Indexed(51)));
+        assert_eq!("#2aa198".parse(), Ok(Color::Rgb(0x2a, 0xa1, 0x98)));
+        assert!("#2aa19".parse::<Color>().is_err());
+        assert!("colour256".parse::<Color>().is_err());
+        assert_eq!(Color::Rgb(0x2a, 0xa1, 0x98).to_string(), "#2aa198");
+    }
+
+    #[test]
+    fn test_downgrade() {
+        use ColorDepth::*;
+        let cyan = Color::Rgb(0x00, 0xff, 0xff);
+        assert_eq!(cyan.downgrade(TrueColor), cyan);
+        assert_eq!(cyan.downgrade(Indexed256), Color::Indexed(51));
+        assert_eq!(cyan.downgrade(Basic), Color::Basic(6));
+        let gray = Color::Rgb(0x3c, 0x38, 0x36);
+        assert_eq!(gray.downgrade(Indexed256), Color::Indexed(237));
+        assert_eq!(Color::Indexed(166).downgrade(Basic), Color::Basic(1));
+        assert_eq!(Color::Indexed(236).downgrade(Basic), Color::Basic(0));
+        assert_eq!(Color::Default.downgrade(Basic), Color::Default);
+    }
+
+    #[test]
+    fn test_builtin_themes() {
+        for (name, _) in BUILTIN_THEMES {
+            let theme = Theme::builtin(name).unwrap();
+            let mut buf = Vec::new();
+            theme.render(&mut buf, ColorDepth::Basic).unwrap();
+            let rendered = String::from_utf8(buf).unwrap();
+            assert!(!rendered.contains('#'), "{}: {}", name, rendered);
+        }
+        let mut buf = Vec::new();
+        let theme = Theme::builtin(DEFAULT_THEME).unwrap();
+        theme.render(&mut buf, ColorDepth::TrueColor).unwrap();
+        let rendered = String::from_utf8(buf).unwrap();
+        assert!(rendered.contains("set-option -g status-style \"fg=colour254,bg=colour236\"\n"));
+        assert!(rendered.contains("set-option -g pane-active-border-style \"fg=colour166\"\n"));
+
+        let mut buf = Vec::new();
+        let theme = Theme::builtin("nord").unwrap();
+        theme.render(&mut buf, ColorDepth::TrueColor).unwrap();
+        let rendered = String::from_utf8(buf).unwrap();
+        assert!(rendered.contains("set-window-option -g mode-style \"fg=#2e3440,bg=#ebcb8b\"\n"));
+        assert!(rendered.contains("set-option -g display-panes-colour \"#4c566a\"\n"));
+    }
+}
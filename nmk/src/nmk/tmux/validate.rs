@@ -0,0 +1,275 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::Deserialize;
+
+use super::config::{parse_end_section, parse_start_section};
+use super::control::{ControlClient, ControlError};
+use crate::consts::bin::TMUX;
+
+/// What to do with rendered config before starting tmux
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidateMode {
+    #[default]
+    Off,
+    /// Log lines rejected by tmux
+    Warn,
+    /// Log and comment out lines rejected by tmux
+    Strip,
+}
+
+/// Line of config rejected by tmux
+#[derive(Debug, Eq, PartialEq)]
+pub struct Problem {
+    /// 1-based line number
+    pub line: usize,
+    pub section: Option<String>,
+    pub command: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(ref section) = self.section {
+            write!(f, " ({})", section)?;
+        }
+        write!(f, ": {}\n    {}", self.message, self.command)
+    }
+}
+
+/// Commands of config with their line number and section
+fn commands(config: &str) -> Vec<(usize, Option<&str>, &str)> {
+    let mut section = None;
+    let mut commands = Vec::new();
+    for (index, line) in config.lines().enumerate() {
+        if let Some(name) = parse_start_section(line) {
+            section = Some(name);
+        } else if parse_end_section(line).is_some() {
+            section = None;
+        }
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            commands.push((index + 1, section, trimmed));
+        }
+    }
+    commands
+}
+
+/// Check each command of `config` on a throwaway tmux server and collect rejected ones
+///
+/// Config is parsed with `source-file -n` where supported, which stops at first error, so
+/// it is repeated with rejected lines commented out. Unknown options are only reported when
+/// `set-option` runs, so option commands are then sent one by one through a control mode
+/// client. Without `-n`, every command is executed to find errors.
+pub fn validate(config: &str) -> Result<Vec<Problem>, ControlError> {
+    // a process may validate several configs at once, e.g. tests
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let socket = format!(
+        "nmk-validate-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let mut client = ControlClient::spawn(
+        &socket,
+        Some(Path::new("/dev/null")),
+        &["new-session", "-s", "validate", "cat"],
+    )?;
+    let socket_path = client
+        .command("display-message -p '#{socket_path}'")
+        .ok()
+        .and_then(|lines| lines.into_iter().next())
+        .map(PathBuf::from);
+    let result = run_commands(&mut client, &socket, config, socket_path.as_deref());
+    client.command("kill-server").unwrap_or_default();
+    client.close()?;
+    if let Some(path) = socket_path {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn run_commands(
+    client: &mut ControlClient,
+    socket: &str,
+    config: &str,
+    socket_path: Option<&Path>,
+) -> Result<Vec<Problem>, ControlError> {
+    let commands = commands(config);
+    let parsed = match socket_path {
+        Some(socket_path) => parse_only(socket, config, &commands, socket_path)?,
+        None => None,
+    };
+    let execute_all = parsed.is_none();
+    let mut problems = parsed.unwrap_or_default();
+    for &(line, section, command) in &commands {
+        if problems.iter().any(|p| p.line == line) || !(execute_all || sets_option(command)) {
+            continue;
+        }
+        match client.command(command) {
+            Ok(_) => {}
+            Err(ControlError::Command(lines)) => problems.push(Problem {
+                line,
+                section: section.map(String::from),
+                command: command.to_string(),
+                message: lines.join("; "),
+            }),
+            Err(e) => return Err(e),
+        }
+    }
+    problems.sort_by_key(|p| p.line);
+    Ok(problems)
+}
+
+fn sets_option(command: &str) -> bool {
+    let name = command.split_whitespace().next().unwrap_or_default();
+    ["set", "set-option", "setw", "set-window-option"].contains(&name)
+}
+
+/// Parse errors of `config`, `None` if tmux doesn't support `source-file -n`
+///
+/// Config is written next to the socket, whose directory is private to current user.
+/// Control mode client doesn't get parse errors as command output, so a normal client
+/// is run instead.
+fn parse_only(
+    socket: &str,
+    config: &str,
+    commands: &[(usize, Option<&str>, &str)],
+    socket_path: &Path,
+) -> Result<Option<Vec<Problem>>, ControlError> {
+    let mut path = socket_path.as_os_str().to_owned();
+    path.push(".conf");
+    let path = PathBuf::from(path);
+    let result = parse_until_clean(socket, config, commands, &path);
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn parse_until_clean(
+    socket: &str,
+    config: &str,
+    commands: &[(usize, Option<&str>, &str)],
+    path: &Path,
+) -> Result<Option<Vec<Problem>>, ControlError> {
+    let path_str = path.to_string_lossy();
+    let mut problems = Vec::new();
+    let mut text = config.to_string();
+    loop {
+        fs::write(path, &text)?;
+        let output = Command::new(TMUX)
+            .args(["-L", socket, "source-file", "-n"])
+            .arg(path)
+            .stdin(Stdio::null())
+            .output()?;
+        if output.status.success() {
+            return Ok(Some(problems));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let error = stdout
+            .lines()
+            .next()
+            .and_then(|l| parse_error(l, &path_str));
+        let (line, message) = match error {
+            Some((line, _)) if problems.iter().any(|p: &Problem| p.line == line) => {
+                return Ok(None)
+            }
+            Some(error) => error,
+            // usage error of tmux older than 3.0
+            None => return Ok(None),
+        };
+        let (section, command) = match commands.iter().find(|c| c.0 == line) {
+            Some(&(_, section, command)) => (section, command),
+            None => (None, config.lines().nth(line - 1).unwrap_or_default()),
+        };
+        problems.push(Problem {
+            line,
+            section: section.map(String::from),
+            command: command.to_string(),
+            message: message.to_string(),
+        });
+        text = strip(config, &problems);
+    }
+}
+
+/// Line number and message of `<path>:<line>: <message>`
+fn parse_error<'a>(error: &'a str, path: &str) -> Option<(usize, &'a str)> {
+    let rest = error.strip_prefix(path)?.strip_prefix(':')?;
+    let (line, message) = rest.split_once(": ")?;
+    Some((line.parse().ok()?, message))
+}
+
+/// Comment out lines of `problems`, line numbers of other lines don't change
+pub fn strip(config: &str, problems: &[Problem]) -> String {
+    let mut result = String::with_capacity(config.len());
+    for (index, line) in config.lines().enumerate() {
+        if problems.iter().any(|p| p.line == index + 1) {
+            result.push_str("# unsupported: ");
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux::config::{render, Context};
+    use crate::tmux::version::Version;
+
+    #[test]
+    fn test_commands_and_strip() {
+        let mut buf = Vec::new();
+        render(&mut buf, &Context::default(), Version::V32a).unwrap();
+        let config = String::from_utf8(buf).unwrap();
+        let commands = commands(&config);
+        let (line, section, command) = commands[0];
+        assert_eq!(section, Some("Tmux Options"));
+        assert_eq!(config.lines().nth(line - 1), Some(command));
+        assert!(commands.iter().any(|c| c.1 == Some("Copy Mode")));
+
+        let problems = vec![Problem {
+            line: 2,
+            section: None,
+            command: "b".to_string(),
+            message: "unknown command: b".to_string(),
+        }];
+        assert_eq!(strip("a\nb\nc", &problems), "a\n# unsupported: b\nc\n");
+    }
+
+    #[test]
+    fn test_validate() {
+        if which::which(TMUX).is_err() {
+            eprintln!("skipped test_validate: {} not found", TMUX);
+            return;
+        }
+        let config = "# ---- Start Options ----\n\
+            set-option -g base-index 1\n\
+            set-option -g no-such-option on\n\
+            # ---- End Options ----\n\
+            no-such-command\n";
+        let problems = validate(config).unwrap();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line, 3);
+        assert_eq!(problems[0].section.as_deref(), Some("Options"));
+        assert!(problems[0].message.contains("no-such-option"));
+        assert_eq!(problems[1].line, 5);
+        assert_eq!(problems[1].section, None);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let path = "/tmp/tmux-0/nmk-validate-1-0.conf";
+        assert_eq!(
+            parse_error(&format!("{}:3: unknown command: x", path), path),
+            Some((3, "unknown command: x"))
+        );
+        assert_eq!(parse_error("usage: source-file [-q] path", path), None);
+        assert!(sets_option("set-option -g base-index 1"));
+        assert!(!sets_option("bind-key x run-shell true"));
+    }
+}
@@ -8,6 +8,7 @@
     PartialOrd,
     strum::AsStaticStr,
     strum::Display,
+    strum::EnumIter,
     strum::EnumString,
 )]
 pub enum Version {